@@ -1,7 +1,18 @@
-use std::process::exit;
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender, RecvTimeoutError};
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::sync::{Arc, Mutex, Once, ONCE_INIT};
+use std::time::{Duration, Instant};
+use std::thread;
 use std::env;
 
+use atty;
+use sha2;
+use digest::Digest;
+use serde_json::{self, Value};
+
 use clap;
 use cargo_shim::{
     Profile,
@@ -13,22 +24,209 @@ use cargo_shim::{
     TargetKind,
     CargoResult,
     MessageFormat,
-    target_to_build_target
+    target_to_build_target,
+    sarif_report
 };
 
-use config::Config;
-use emscripten::initialize_emscripten;
+use config::{Config, BuildStd, Defaults, WASM_OPT_LEVELS};
+use emscripten::{initialize_emscripten, clear_emscripten_cache};
 use error::Error;
-use wasm;
+use wasm::{self, BuildInfo, ProcessOptions};
+use wasm_analyze_deps;
+use wasm_size;
+use wasm_runtime::RuntimeKind;
+use wasm_typescript::is_identifier;
+use utils::{read, write, set_json_diagnostics, set_quiet};
+
+fn is_nightly_toolchain() -> bool {
+    Command::new( "rustc" )
+        .arg( "--version" )
+        .output()
+        .map( |output| String::from_utf8_lossy( &output.stdout ).contains( "nightly" ) )
+        .unwrap_or( false )
+}
+
+// Returns `None` when rustup isn't there to ask, e.g. with a toolchain
+// installed through the system's package manager.
+fn installed_rustup_targets() -> Option< Vec< String > > {
+    let output = Command::new( "rustup" )
+        .args( &[ "target", "list", "--installed" ] )
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some( String::from_utf8_lossy( &output.stdout ).lines().map( |line| line.trim().to_owned() ).collect() )
+}
+
+fn rustc_version() -> String {
+    Command::new( "rustc" )
+        .arg( "-vV" )
+        .output()
+        .map( |output| String::from_utf8_lossy( &output.stdout ).trim().to_owned() )
+        .unwrap_or_default()
+}
+
+fn build_std_args( build_std: &BuildStd ) -> Vec< String > {
+    let mut args = Vec::new();
+    args.push( "-Z".to_owned() );
+    match build_std.crates {
+        Some( ref crates ) if !crates.is_empty() => args.push( format!( "build-std={}", crates.join( "," ) ) ),
+        _ => args.push( "build-std".to_owned() )
+    }
+
+    if let Some( ref features ) = build_std.features {
+        if !features.is_empty() {
+            args.push( "-Z".to_owned() );
+            args.push( format!( "build-std-features={}", features.join( "," ) ) );
+        }
+    }
+
+    args
+}
+
+const TRIPLETS: &'static [&'static str] = &[
+    "asmjs-unknown-emscripten",
+    "wasm32-unknown-emscripten",
+    "wasm32-unknown-unknown"
+];
+
+// Like cargo this accepts both spaces and commas as separators.
+// Features of dependencies (`dependency/feature`) aren't checked
+// since we only know about the features of the package itself.
+fn undeclared_features< 'a >( requested: &[&'a str], declared: &[String] ) -> Vec< &'a str > {
+    requested.iter()
+        .cloned()
+        .filter( |feature| !feature.contains( '/' ) && !declared.iter().any( |declared| declared == feature ) )
+        .collect()
+}
+
+fn split_features( features: &str ) -> Vec< &str > {
+    let mut output = Vec::new();
+    for feature in features.split( |ch: char| ch == ',' || ch.is_whitespace() ) {
+        if !feature.is_empty() && !output.contains( &feature ) {
+            output.push( feature );
+        }
+    }
+
+    output
+}
 
 pub struct BuildArgsMatcher< 'a > {
     pub matches: &'a clap::ArgMatches< 'a >,
-    pub project: &'a CargoProject
+    pub project: &'a CargoProject,
+    pub config: Config,
+    pub sarif_log: Option< Arc< SarifLog > >,
+    /// Builds in release mode even without `--release`.
+    pub force_release: bool
 }
 
 impl< 'a > BuildArgsMatcher< 'a > {
+    pub fn new( matches: &'a clap::ArgMatches< 'a >, project: &'a CargoProject ) -> Result< Self, Error > {
+        let mut matcher = BuildArgsMatcher {
+            matches,
+            project,
+            config: Config::default(),
+            sarif_log: matches.value_of( "sarif" ).map( |path| Arc::new( SarifLog::new( path ) ) ),
+            force_release: false
+        };
+
+        // This has to happen before anything gets printed, e.g. the warnings about `Web.toml`.
+        set_json_diagnostics( matcher.message_format() == MessageFormat::Json );
+        set_quiet( matcher.matches.is_present( "quiet" ) );
+        matcher.check_target_flags()?;
+
+        let package = matcher.package_or_default()?;
+        matcher.load_package_settings( package )?;
+        Ok( matcher )
+    }
+
+    /// A matcher for one of the members of the workspace, which resolves
+    /// the target and the `[defaults]` from that member's own `Web.toml`.
+    pub fn for_member( &self, package: &CargoPackage ) -> Result< BuildArgsMatcher< 'a >, Error > {
+        let mut matcher = BuildArgsMatcher {
+            matches: self.matches,
+            project: self.project,
+            config: Config::default(),
+            sarif_log: self.sarif_log.clone(),
+            force_release: self.force_release
+        };
+
+        matcher.load_package_settings( package )?;
+        Ok( matcher )
+    }
+
+    fn load_package_settings( &mut self, package: &CargoPackage ) -> Result< (), Error > {
+        // The base `Web.toml` is needed first since its `[defaults]`
+        // decide which of the target-specific overlays applies.
+        self.config = Config::load_for_package( package, None )
+            .map_err( |err| Error::ConfigurationError( format!( "cannot load your `Web.toml`: {}", err ) ) )?
+            .map( |(config, _)| config )
+            .unwrap_or_default();
+
+        // This is the same setting as `target` in `[defaults]`, which wins if both are set.
+        if self.config.build.as_ref().and_then( |build| build.default_target.as_ref() ).is_some() {
+            println_err!( "warning: `default-target` in the `[build]` of your `Web.toml` is deprecated; use `target` in `[defaults]` instead" );
+        }
+
+        if let Some( triplet ) = self.default_triplet() {
+            if !TRIPLETS.contains( &triplet ) {
+                let key = if self.defaults().and_then( |defaults| defaults.target.as_ref() ).is_some() { "`target` in the `[defaults]`" } else { "`default-target` in the `[build]`" };
+                return Err( Error::ConfigurationError( format!(
+                    "unknown {} of your `Web.toml`: `{}`; expected one of: {}",
+                    key,
+                    triplet,
+                    TRIPLETS.join( ", " )
+                )));
+            }
+        }
+
+        self.config = self.load_config( package )?;
+        self.check_feature_flags( package )?;
+
+        // The system's Emscripten can also be enabled through the `[defaults]`
+        // or the environment, so this can't be left to clap.
+        if self.matches.is_present( "strict" ) && !self.use_system_emscripten() {
+            return Err( Error::ConfigurationError( "`--strict` only applies when the system's Emscripten is used, e.g. through `--use-system-emscripten`".into() ) );
+        }
+
+        if self.matches.is_present( "no-emscripten-cache" ) && !self.targeting_emscripten() {
+            println_err!( "warning: `--no-emscripten-cache` is only supported on the Emscripten targets; ignoring" );
+        }
+
+        Ok(())
+    }
+
+    pub fn config( &self ) -> &Config {
+        &self.config
+    }
+
+    // Along with the overlay for the target which is being built.
+    fn load_config( &self, package: &CargoPackage ) -> Result< Config, Error > {
+        let overlay = if self.targeting_native_wasm() { "wasm" } else { "emscripten" };
+        Config::load_for_package_printing_warnings( package, Some( overlay ) )
+            .map_err( |err| Error::ConfigurationError( format!( "cannot load your `Web.toml`: {}", err ) ) )
+            .map( |config| config.unwrap_or_default() )
+    }
+
+    fn defaults( &self ) -> Option< &Defaults > {
+        self.config.defaults.as_ref()
+    }
+
+    fn default_triplet( &self ) -> Option< &str > {
+        self.defaults().and_then( |defaults| defaults.target.as_ref() )
+            .or_else( || self.config.build.as_ref().and_then( |build| build.default_target.as_ref() ) )
+            .map( |target| target.as_str() )
+    }
+
     fn requested_build_type( &self ) -> BuildType {
-        if self.matches.is_present( "release" ) {
+        let release = self.force_release ||
+            self.matches.is_present( "release" ) ||
+            self.defaults().and_then( |defaults| defaults.release ).unwrap_or( false );
+
+        if release {
             BuildType::Release
         } else {
             BuildType::Debug
@@ -40,11 +238,11 @@ impl< 'a > BuildArgsMatcher< 'a > {
     }
 
     pub fn targeting_emscripten_wasm( &self ) -> bool {
-        self.matches.is_present( "target-webasm-emscripten" )
+        self.triplet_or_default() == "wasm32-unknown-emscripten"
     }
 
     pub fn targeting_native_wasm( &self ) -> bool {
-        self.matches.is_present( "target-webasm" )
+        self.triplet_or_default() == "wasm32-unknown-unknown"
     }
 
     pub fn targeting_wasm( &self ) -> bool {
@@ -56,7 +254,23 @@ impl< 'a > BuildArgsMatcher< 'a > {
     }
 
     fn use_system_emscripten( &self ) -> bool {
-        self.matches.is_present( "use-system-emscripten" )
+        if self.matches.is_present( "use-system-emscripten" ) {
+            return true;
+        }
+
+        if let Some( value ) = env::var_os( "CARGO_WEB_USE_SYSTEM_EMSCRIPTEN" ) {
+            return value != "0" && value != "false";
+        }
+
+        self.defaults().and_then( |defaults| defaults.use_system_emscripten ).unwrap_or( false )
+    }
+
+    fn relative_paths_root( &self ) -> Option< PathBuf > {
+        if self.matches.is_present( "relative-paths" ) {
+            Some( self.project.workspace_root.clone() )
+        } else {
+            None
+        }
     }
 
     fn message_format( &self ) -> MessageFormat {
@@ -71,15 +285,55 @@ impl< 'a > BuildArgsMatcher< 'a > {
         }
     }
 
+    fn use_color( &self ) -> bool {
+        match self.matches.value_of( "color" ) {
+            Some( "always" ) => true,
+            Some( "never" ) => false,
+            // The compiler's messages are printed to stderr.
+            _ => atty::is( atty::Stream::Stderr )
+        }
+    }
+
     fn is_verbose( &self ) -> bool {
         self.matches.is_present( "verbose" )
     }
 
+    fn verbosity( &self ) -> u64 {
+        self.matches.occurrences_of( "verbose" )
+    }
+
+    fn heartbeat_interval( &self ) -> Option< Duration > {
+        // It'd end up on stdout in between cargo's own messages.
+        if self.message_format() == MessageFormat::Json {
+            return None;
+        }
+
+        let seconds = match self.matches.value_of( "build-heartbeat" ) {
+            // This was already validated by clap.
+            Some( value ) => value.parse().unwrap(),
+            // CI systems tend to kill jobs which haven't printed
+            // anything for a while, so keep them fed by default.
+            None if !atty::is( atty::Stream::Stdout ) => 60,
+            None => 0
+        };
+
+        if seconds == 0 {
+            None
+        } else {
+            Some( Duration::from_secs( seconds ) )
+        }
+    }
+
     fn build_type( &self ) -> BuildType {
         let build_type = self.requested_build_type();
         if self.targeting_native_wasm() && build_type == BuildType::Debug {
             // TODO: Remove this in the future.
             println_err!( "warning: debug builds on the wasm-unknown-unknown are currently totally broken" );
+            if self.matches.is_present( "no-force-release" ) {
+                println_err!( "         building in debug mode anyway since `--no-force-release` was passed" );
+                return build_type;
+            }
+
             println_err!( "         forcing a release build" );
             return BuildType::Release;
         }
@@ -87,7 +341,7 @@ impl< 'a > BuildArgsMatcher< 'a > {
         build_type
     }
 
-    fn package( &self ) -> Result< Option< &CargoPackage >, Error > {
+    fn package( &self ) -> Result< Option< &'a CargoPackage >, Error > {
         if let Some( name ) = self.matches.value_of( "package" ) {
             match self.project.packages.iter().find( |package| package.name == name ) {
                 None => Err( Error::ConfigurationError( format!( "package `{}` not found", name ) ) ),
@@ -98,71 +352,146 @@ impl< 'a > BuildArgsMatcher< 'a > {
         }
     }
 
-    pub fn package_or_default( &self ) -> Result< &CargoPackage, Error > {
+    pub fn package_or_default( &self ) -> Result< &'a CargoPackage, Error > {
         Ok( self.package()?.unwrap_or_else( || self.project.default_package() ) )
     }
 
-    fn target( &'a self, package: &'a CargoPackage ) -> Result< Option< &'a CargoTarget >, Error > {
+    // Empty when no target was explicitly picked.
+    fn target( &'a self, package: &'a CargoPackage ) -> Result< Vec< &'a CargoTarget >, Error > {
         let targets = &package.targets;
         if self.matches.is_present( "lib" ) {
             match targets.iter().find( |target| target.kind == TargetKind::Lib ) {
                 None => return Err( Error::ConfigurationError( format!( "no library targets found" ) ) ),
-                target => Ok( target )
+                Some( target ) => Ok( vec![ target ] )
             }
-        } else if let Some( name ) = self.matches.value_of( "bin" ) {
-            match targets.iter().find( |target| target.kind == TargetKind::Bin && target.name == name ) {
-                None => return Err( Error::ConfigurationError( format!( "no bin target named `{}`", name ) ) ),
-                target => Ok( target )
+        } else if let Some( names ) = self.matches.values_of( "bin" ) {
+            let mut selected = Vec::new();
+            for name in names {
+                match targets.iter().find( |target| target.kind == TargetKind::Bin && target.name == name ) {
+                    None => return Err( Error::ConfigurationError( format!( "no bin target named `{}`", name ) ) ),
+                    Some( target ) => {
+                        if !selected.iter().any( |selected: &&CargoTarget| selected.name == target.name ) {
+                            selected.push( target );
+                        }
+                    }
+                }
             }
+            Ok( selected )
         } else if let Some( name ) = self.matches.value_of( "example" ) {
             match targets.iter().find( |target| target.kind == TargetKind::Example && target.name == name ) {
                 None => return Err( Error::ConfigurationError( format!( "no example target named `{}`", name ) ) ),
-                target => Ok( target )
+                Some( target ) => Ok( vec![ target ] )
             }
         } else if let Some( name ) = self.matches.value_of( "bench" ) {
             match targets.iter().find( |target| target.kind == TargetKind::Bench && target.name == name ) {
                 None => return Err( Error::ConfigurationError( format!( "no bench target named `{}`", name ) ) ),
-                target => Ok( target )
+                Some( target ) => Ok( vec![ target ] )
             }
         } else {
-            Ok( None )
+            Ok( Vec::new() )
         }
     }
 
     pub fn target_or_select< F >( &'a self, package: &'a CargoPackage, filter: F ) -> Result< Vec< &'a CargoTarget >, Error >
         where for< 'r > F: Fn( &'r CargoTarget ) -> bool
     {
-        Ok( self.target( package )?.map( |target| vec![ target ] ).unwrap_or_else( || {
-            package.targets.iter().filter( |target| filter( target ) ).collect()
-        }))
+        let all_bins = self.matches.is_present( "bins" );
+        let all_examples = self.matches.is_present( "examples" );
+        if all_bins && self.matches.is_present( "bin" ) {
+            return Err( Error::ConfigurationError( "`--bins` can't be combined with `--bin`".into() ) );
+        }
+
+        if all_examples && self.matches.is_present( "example" ) {
+            return Err( Error::ConfigurationError( "`--examples` can't be combined with `--example`".into() ) );
+        }
+
+        if all_bins || all_examples {
+            let targets: Vec< _ > = package.targets.iter().filter( |target| {
+                (all_bins && target.kind == TargetKind::Bin) || (all_examples && target.kind == TargetKind::Example)
+            }).collect();
+
+            if targets.is_empty() {
+                return Err( Error::ConfigurationError( format!( "no {} targets found", if all_bins { "bin" } else { "example" } ) ) );
+            }
+
+            return Ok( targets );
+        }
+
+        let targets = self.target( package )?;
+        if !targets.is_empty() {
+            return Ok( targets );
+        }
+
+        Ok( package.targets.iter().filter( |target| filter( target ) ).collect() )
     }
 
-    fn triplet_or_default( &self ) -> &str {
+    pub fn triplet_or_default( &self ) -> &str {
         if self.matches.is_present( "target-webasm") {
             "wasm32-unknown-unknown"
         } else if self.matches.is_present( "target-webasm-emscripten" ) {
             "wasm32-unknown-emscripten"
-        } else {
+        } else if self.matches.is_present( "target-asmjs-emscripten" ) {
             "asmjs-unknown-emscripten"
+        } else {
+            self.default_triplet().unwrap_or( "asmjs-unknown-emscripten" )
         }
     }
 
+    // Otherwise `triplet_or_default` would silently pick one of them.
+    fn check_target_flags( &self ) -> Result< (), Error > {
+        let passed: Vec< _ > = [ "target-webasm", "target-webasm-emscripten", "target-asmjs-emscripten" ].iter()
+            .filter( |flag| self.matches.is_present( flag ) )
+            .map( |flag| format!( "`--{}`", flag ) )
+            .collect();
+
+        if passed.len() > 1 {
+            return Err( Error::ConfigurationError( format!( "conflicting target flags: {}; please pass only one of them", passed.join( ", " ) ) ) );
+        }
+
+        Ok(())
+    }
+
+    fn check_feature_flags( &self, package: &CargoPackage ) -> Result< (), Error > {
+        if self.matches.is_present( "all-features" ) && self.matches.is_present( "no-default-features" ) {
+            return Err( Error::ConfigurationError( "`--all-features` and `--no-default-features` can't be used together".into() ) );
+        }
+
+        if let Some( features ) = self.matches.value_of( "features" ) {
+            let undeclared = undeclared_features( &split_features( features ), &package.features );
+            if !undeclared.is_empty() {
+                println_err!( "warning: package `{}` doesn't declare the following features: {}", package.name, undeclared.join( ", " ) );
+            }
+        }
+
+        Ok(())
+    }
+
     fn features( &self ) -> Vec< &str > {
         if let Some( features ) = self.matches.value_of( "features" ) {
-            features.split_whitespace().collect()
-        } else {
+            split_features( features )
+        } else if self.matches.is_present( "all-features" ) {
             Vec::new()
+        } else {
+            self.defaults()
+                .and_then( |defaults| defaults.features.as_ref() )
+                .map( |features| features.iter().map( |feature| feature.as_str() ).collect() )
+                .unwrap_or_else( Vec::new )
         }
     }
 
-    pub fn prepare_builder( &self, config: &Config, package: &CargoPackage, target: &CargoTarget, profile: Profile ) -> Builder {
+    pub fn prepare_builder( &self, config: &Config, package: &CargoPackage, target: &CargoTarget, profile: Profile ) -> Result< Builder, Error > {
         let mut extra_paths = Vec::new();
         let mut extra_rustflags = Vec::new();
         let mut extra_environment = Vec::new();
+        let mut extra_cargo_args = Vec::new();
 
         if self.targeting_emscripten() {
-            if let Some( emscripten ) = initialize_emscripten( self.use_system_emscripten(), self.targeting_wasm() ) {
+            let pinned_version = config.emscripten.as_ref().and_then( |emscripten| emscripten.version.as_ref() ).map( |version| version.as_str() );
+            let strict_version = config.emscripten.as_ref().and_then( |emscripten| emscripten.strict ).unwrap_or( false );
+            let no_fallback = self.matches.is_present( "strict" );
+            if let Some( emscripten ) = initialize_emscripten( self.use_system_emscripten(), no_fallback, self.targeting_wasm(), pinned_version, strict_version )? {
                 extra_paths.push( emscripten.emscripten_path.clone() );
+                extra_environment.push( ("EM_CACHE".to_owned(), emscripten.cache_path.to_string_lossy().into_owned()) );
 
                 let emscripten_path = emscripten.emscripten_path.to_string_lossy().into_owned();
                 let emscripten_llvm_path = emscripten.emscripten_llvm_path.to_string_lossy().into_owned();
@@ -187,21 +516,30 @@ impl< 'a > BuildArgsMatcher< 'a > {
             extra_rustflags.push( format!( "link-arg=NO_EXIT_RUNTIME={}", exit_runtime as u32 ) );
         }
 
-        if let Some( ref link_args ) = config.link_args {
-            for arg in link_args {
-                if arg.contains( " " ) {
-                    // Not sure how to handle spaces, as `-C link-arg="{}"` doesn't work.
-                    println_err!( "error: you have a space in one of the entries in `link-args` in your `Web.toml`;" );
-                    println_err!( "       this is currently unsupported - aborting!" );
-                    exit( 101 );
-                }
+        if let Some( sanitizer ) = self.matches.value_of( "sanitizer" ) {
+            if !self.targeting_emscripten() {
+                return Err( Error::ConfigurationError( "`--sanitizer` is only supported on the Emscripten targets".into() ) );
+            }
 
+            // Only the code which Emscripten compiles itself gets instrumented; the Rust
+            // code only benefits from the checks done by the runtime, e.g. in `free`.
+            extra_rustflags.push( "-C".to_owned() );
+            extra_rustflags.push( format!( "link-arg=-fsanitize={}", sanitizer ) );
+            if sanitizer == "address" {
+                // The shadow memory doesn't fit into Emscripten's default heap.
+                extra_rustflags.push( "-C".to_owned() );
+                extra_rustflags.push( "link-arg=-s".to_owned() );
                 extra_rustflags.push( "-C".to_owned() );
-                extra_rustflags.push( format!( "link-arg={}", arg ) );
+                extra_rustflags.push( "link-arg=ALLOW_MEMORY_GROWTH=1".to_owned() );
             }
         }
 
-        if self.targeting_native_wasm() && self.requested_build_type() == BuildType::Debug {
+        for arg in config.link_args_for( self.triplet_or_default() ) {
+            extra_rustflags.push( "-C".to_owned() );
+            extra_rustflags.push( format!( "link-arg={}", arg ) );
+        }
+
+        if self.matches.is_present( "keep-debug" ) || (self.targeting_native_wasm() && self.requested_build_type() == BuildType::Debug) {
             extra_rustflags.push( "-C".to_owned() );
             extra_rustflags.push( "debuginfo=2".to_owned() );
         }
@@ -214,43 +552,654 @@ impl< 'a > BuildArgsMatcher< 'a > {
             }
         }
 
-        Builder::new( BuildConfig {
+        // Otherwise the build fails with a rather cryptic "can't find crate for `std`".
+        // With `build-std` the standard library gets built from source, so then it's not needed.
+        if self.targeting_native_wasm() && config.build_std.is_none() {
+            let triplet = self.triplet_or_default();
+            if let Some( targets ) = installed_rustup_targets() {
+                if !targets.iter().any( |target| target == triplet ) {
+                    return Err( Error::ConfigurationError( format!(
+                        "the `{}` target isn't installed; run: rustup target add {}",
+                        triplet,
+                        triplet
+                    )));
+                }
+            }
+        }
+
+        if let Some( ref build_std ) = config.build_std {
+            if !self.targeting_native_wasm() {
+                println_err!( "warning: `build-std` from your `Web.toml` is only supported on the native wasm target; ignoring" );
+            } else if !is_nightly_toolchain() {
+                return Err( Error::ConfigurationError(
+                    "`build-std` in your `Web.toml` requires a nightly Rust toolchain; try running `rustup override set nightly`".into()
+                ));
+            } else {
+                extra_cargo_args.extend( build_std_args( build_std ) );
+            }
+        }
+
+        if let Some( flags ) = self.matches.values_of( "unstable-flags" ) {
+            for flag in flags {
+                extra_cargo_args.push( "-Z".to_owned() );
+                extra_cargo_args.push( flag.to_owned() );
+            }
+        }
+
+        if let Some( jobs ) = self.matches.value_of( "jobs" ) {
+            match jobs.parse::< u32 >() {
+                Ok( jobs ) if jobs > 0 => {
+                    extra_cargo_args.push( "--jobs".to_owned() );
+                    extra_cargo_args.push( jobs.to_string() );
+                },
+                _ => return Err( Error::ConfigurationError( format!( "invalid `--jobs` value `{}`; expected a positive number", jobs ) ) )
+            }
+        }
+
+        // Cargo itself reports when the lockfile would have to change.
+        for flag in &[ "locked", "frozen" ] {
+            if self.matches.is_present( flag ) {
+                extra_cargo_args.push( format!( "--{}", flag ) );
+            }
+        }
+
+        // The artifacts' paths come from cargo's output, so nothing
+        // else has to know where the target directory is.
+        if let Some( target_dir ) = self.matches.value_of_os( "target-dir" ) {
+            let current_dir = env::current_dir()
+                .map_err( |err| Error::RuntimeError( "cannot get the current directory".into(), err.into() ) )?;
+            let target_dir = current_dir.join( target_dir );
+            extra_cargo_args.push( "--target-dir".to_owned() );
+            extra_cargo_args.push( target_dir.to_string_lossy().into_owned() );
+        }
+
+        if let Some( args ) = self.matches.values_of( "cargo-arg" ) {
+            extra_cargo_args.extend( args.map( |arg| arg.to_owned() ) );
+        }
+
+        let mut features: Vec< String > = self.features().into_iter().map( |feature| feature.to_owned() ).collect();
+        if let Some( target_features ) = config.target_features.as_ref().and_then( |target_features| target_features.get( &target.name ) ) {
+            for feature in target_features {
+                if !features.contains( feature ) {
+                    features.push( feature.clone() );
+                }
+            }
+        }
+
+        if let Some( allocator ) = self.matches.value_of( "allocator" ) {
+            let feature = config.allocators.as_ref().and_then( |allocators| allocators.get( allocator ) );
+            match feature {
+                Some( feature ) => features.push( feature.clone() ),
+                None => {
+                    let known: Vec< &str > = config.allocators.iter().flat_map( |allocators| allocators.keys() ).map( |key| key.as_str() ).collect();
+                    return Err( Error::ConfigurationError( format!(
+                        "unknown allocator `{}`; the `[allocators]` table in your `Web.toml` defines: {}",
+                        allocator,
+                        if known.is_empty() { "nothing".to_owned() } else { known.join( ", " ) }
+                    )));
+                }
+            }
+        }
+
+        let emitted_extensions: Option< Vec< String > > = self.matches.values_of( "emit" ).map( |values| values.map( |value| value.to_owned() ).collect() );
+        let emits = |kind: &str| emitted_extensions.as_ref().map( |extensions| extensions.iter().any( |extension| extension == kind ) ).unwrap_or( true );
+        if !self.targeting_wasm() && !emits( "js" ) {
+            return Err( Error::ConfigurationError( "the asm.js target only produces `.js` files, so `--emit` has to include `js`".into() ) );
+        }
+
+        if emitted_extensions.is_some() && emits( "html" ) && !emits( "js" ) {
+            return Err( Error::ConfigurationError( "the page generated for `--emit html` loads the `.js` file, so `--emit` has to include `js` too".into() ) );
+        }
+
+        // Only the `.js` file made by Emscripten can't be left out, and asking
+        // for a kind which is otherwise opt-in through `--emit` also turns it on.
+        let skip_js = self.targeting_native_wasm() && !emits( "js" );
+        let emits_typescript = emitted_extensions.is_some() && emits( "ts" );
+        let emits_html = emitted_extensions.is_some() && emits( "html" );
+        let emits_signature = emits( "sig" );
+
+        let build_config = BuildConfig {
             build_target: target_to_build_target( target, profile ),
             build_type: self.build_type(),
             triplet: Some( self.triplet_or_default().into() ),
             package: Some( package.name.clone() ),
-            features: self.features().into_iter().map( |feature| feature.to_owned() ).collect(),
+            features,
             no_default_features: self.matches.is_present( "no-default-features" ),
             enable_all_features: self.matches.is_present( "all-features" ),
             extra_paths,
             extra_rustflags,
             extra_environment,
+            extra_cargo_args,
             message_format: self.message_format(),
-            is_verbose: self.is_verbose()
+            relative_paths_root: self.relative_paths_root(),
+            emitted_extensions,
+            use_color: self.use_color(),
+            is_verbose: self.is_verbose(),
+            check_only: false
+        };
+
+        let mut wasm_options = ProcessOptions::default();
+        if config.build_info.unwrap_or( false ) {
+            wasm_options.build_info = Some( BuildInfo {
+                version: package.version.clone(),
+                crate_root: package.crate_root.clone()
+            });
+        }
+
+        wasm_options.wasm_opt_args = config.wasm_opt_args.clone();
+        if let Some( level ) = config.build.as_ref().and_then( |build| build.wasm_opt_level.as_ref() ) {
+            if !WASM_OPT_LEVELS.contains( &level.as_str() ) {
+                return Err( Error::ConfigurationError( format!(
+                    "unknown `wasm-opt-level` in the `[build]` of your `Web.toml`: `{}`; expected one of: {}",
+                    level,
+                    WASM_OPT_LEVELS.join( ", " )
+                )));
+            }
+
+            if self.targeting_native_wasm() {
+                wasm_options.wasm_opt_level = Some( level.clone() );
+            } else {
+                println_err!( "warning: `wasm-opt-level` from your `Web.toml` is only supported on the native wasm target; ignoring" );
+            }
+        }
+        wasm_options.allowed_imports = config.allowed_imports.clone();
+        if emits_signature {
+            wasm_options.sign_command = config.sign_command.clone();
+        }
+        if let Some( ref host_imports ) = config.host_imports {
+            if !self.targeting_native_wasm() {
+                println_err!( "warning: `host-imports` from your `Web.toml` is only supported on the native wasm target; ignoring" );
+            } else {
+                let path = package.crate_root.join( host_imports );
+                let code = read( &path ).map_err( |error| {
+                    Error::RuntimeError( format!( "cannot read {:?} which `host-imports` in your `Web.toml` refers to", path ), error.into() )
+                })?;
+
+                wasm_options.host_imports = Some( code );
+            }
+        }
+
+        wasm_options.keep_debug = self.matches.is_present( "keep-debug" );
+        wasm_options.normalize = self.matches.is_present( "normalize" );
+        if wasm_options.normalize && !self.targeting_native_wasm() {
+            println_err!( "warning: `--normalize` is only supported on the native wasm target; ignoring" );
+        }
+
+        if let Some( runtime ) = self.matches.value_of( "runtime" ) {
+            if !self.targeting_native_wasm() {
+                println_err!( "warning: `--runtime` is only supported on the native wasm target; ignoring" );
+            } else {
+                wasm_options.runtime = RuntimeKind::from_str( runtime ).unwrap();
+            }
+        }
+
+        wasm_options.skip_js = skip_js;
+        wasm_options.typescript = self.matches.is_present( "typescript" ) || emits_typescript;
+        if wasm_options.typescript && !self.targeting_native_wasm() {
+            println_err!( "warning: `--typescript` is only supported on the native wasm target; ignoring" );
+        }
+
+        let module_name = self.matches.value_of( "module-name" ).map( |name| name.to_owned() )
+            .or_else( || config.build.as_ref().and_then( |build| build.module_name.clone() ) );
+        if let Some( module_name ) = module_name {
+            if !is_identifier( &module_name ) {
+                return Err( Error::ConfigurationError( format!( "the module name `{}` isn't a valid JavaScript identifier", module_name ) ) );
+            }
+
+            if self.targeting_native_wasm() {
+                wasm_options.module_name = Some( module_name );
+            } else {
+                println_err!( "warning: a custom module name is only supported on the native wasm target; ignoring" );
+            }
+        }
+
+        wasm_options.generate_html = self.matches.is_present( "generate-html" ) || emits_html;
+        wasm_options.overwrite_html = self.matches.is_present( "force" );
+        wasm_options.content_security_policy = config.content_security_policy.as_ref()
+            .and_then( |policy| policy.directives() )
+            .map( |directives| directives.to_owned() );
+        if wasm_options.generate_html && !self.targeting_native_wasm() {
+            println_err!( "warning: `--generate-html` is only supported on the native wasm target; ignoring" );
+        }
+
+        let expected_features = if self.matches.is_present( "locked-features" ) {
+            match config.expected_features {
+                Some( ref expected_features ) => Some( expected_features.clone() ),
+                None => return Err( Error::ConfigurationError( "`--locked-features` requires an `[expected-features]` table in your `Web.toml`".into() ) )
+            }
+        } else {
+            None
+        };
+
+        let clear_emscripten_cache = self.targeting_emscripten() && self.matches.is_present( "no-emscripten-cache" );
+        if clear_emscripten_cache && self.use_system_emscripten() {
+            static WARNING: Once = ONCE_INIT;
+            WARNING.call_once( || {
+                println_err!( "warning: `--no-emscripten-cache` only clears the cache of the prebuilt Emscripten; use `cargo web emscripten clear-cache --global` to also clear the system one's" );
+            });
+        }
+
+        Ok( Builder {
+            build_config,
+            wasm_options,
+            clear_emscripten_cache,
+            heartbeat_interval: self.heartbeat_interval(),
+            expected_features,
+            sarif_log: self.sarif_log.clone(),
+            analyze_deps: self.matches.is_present( "analyze-deps" ),
+            print_wasm_size: self.matches.is_present( "print-wasm-size" ),
+            print_build_config: self.verbosity() >= 2,
+            expanded_variables: config.expanded_variables.clone()
         })
     }
 }
 
-pub struct Builder( BuildConfig );
+fn spawn_heartbeat( interval: Duration ) -> Sender< () > {
+    let (tx, rx) = channel();
+    thread::spawn( move || {
+        let start = Instant::now();
+        while let Err( RecvTimeoutError::Timeout ) = rx.recv_timeout( interval ) {
+            println_err!( "    Still building... ({}s elapsed)", start.elapsed().as_secs() );
+        }
+    });
 
-impl Builder {
-    pub fn new( build_config: BuildConfig ) -> Self {
-        Builder( build_config )
+    tx
+}
+
+// Returns the `(crate, feature)` pairs which were activated even
+// though they're not listed for that crate in `expected`. Crates
+// which aren't listed at all aren't checked.
+fn find_unexpected_features( expected: &BTreeMap< String, Vec< String > >, activated: &[(String, Vec< String >)] ) -> Vec< (String, String) > {
+    let mut unexpected = Vec::new();
+    for &(ref name, ref features) in activated {
+        let expected_features = match expected.get( name ) {
+            Some( expected_features ) => expected_features,
+            None => continue
+        };
+
+        for feature in features {
+            let pair = (name.clone(), feature.clone());
+            if !expected_features.contains( feature ) && !unexpected.contains( &pair ) {
+                unexpected.push( pair );
+            }
+        }
+    }
+
+    unexpected
+}
+
+/// Collects the diagnostics of every build started by a single
+/// command, since e.g. `cargo web test` builds more than one target.
+pub struct SarifLog {
+    path: PathBuf,
+    results: Mutex< BTreeMap< String, Vec< Value > > >
+}
+
+impl SarifLog {
+    fn new< P: Into< PathBuf > >( path: P ) -> Self {
+        SarifLog {
+            path: path.into(),
+            results: Mutex::new( BTreeMap::new() )
+        }
+    }
+
+    // Rebuilds replace the results of their previous run.
+    fn update( &self, key: String, results: &[Value] ) -> Result< (), Error > {
+        let mut all_results = self.results.lock().unwrap();
+        all_results.insert( key, results.to_vec() );
+
+        let results = all_results.values().flat_map( |results| results.iter().cloned() ).collect();
+        let report = serde_json::to_string_pretty( &sarif_report( results ) ).unwrap();
+        write( &self.path, &report ).map_err( |error| {
+            Error::RuntimeError( format!( "cannot write the SARIF report to {:?}", self.path ), error.into() )
+        })
+    }
+}
+
+// For `-vv`; everything is printed as-is since it's meant for debugging.
+fn print_build_config( build: &BuildConfig ) {
+    println_err!( "    Build configuration for {:?}:", build.build_target );
+    println_err!( "      triplet: {}", build.triplet.as_ref().map( |triplet| triplet.as_str() ).unwrap_or( "(host)" ) );
+    println_err!( "      build type: {:?}", build.build_type );
+    println_err!( "      features: {:?}", build.features );
+    println_err!( "      rustflags: {:?}", build.extra_rustflags );
+    println_err!( "      cargo args: {:?}", build.extra_cargo_args );
+    println_err!( "      extra paths: {:?}", build.extra_paths );
+    println_err!( "      environment:" );
+    for &(ref key, ref value) in &build.extra_environment {
+        println_err!( "        {}={}", key, value );
+    }
+}
+
+pub struct Builder {
+    build_config: BuildConfig,
+    wasm_options: ProcessOptions,
+    clear_emscripten_cache: bool,
+    heartbeat_interval: Option< Duration >,
+    expected_features: Option< BTreeMap< String, Vec< String > > >,
+    sarif_log: Option< Arc< SarifLog > >,
+    analyze_deps: bool,
+    print_wasm_size: bool,
+    print_build_config: bool,
+    expanded_variables: BTreeMap< String, String >
+}
+
+// The directory the artifacts go into and the number of jobs
+// don't affect what gets built.
+const UNFINGERPRINTED_CARGO_ARGS: &'static [&'static str] = &[ "--target-dir", "--jobs" ];
+
+fn fingerprinted_cargo_args( args: &[String] ) -> Vec< &str > {
+    let mut output = Vec::new();
+    let mut args = args.iter();
+    while let Some( arg ) = args.next() {
+        if UNFINGERPRINTED_CARGO_ARGS.contains( &arg.as_str() ) {
+            args.next();
+        } else {
+            output.push( arg.as_str() );
+        }
     }
 
+    output
+}
+
+// Puts back the references to the environment variables from `Web.toml`,
+// since their values (e.g. `${PREFIX}`) are usually specific to the machine.
+fn unexpand_variables( value: &str, variables: &BTreeMap< String, String > ) -> String {
+    let mut variables: Vec< (&String, &String) > = variables.iter().filter( |&(_, value)| !value.is_empty() ).collect();
+    variables.sort_by_key( |&(_, value)| ::std::cmp::Reverse( value.len() ) );
+
+    let mut output = value.to_owned();
+    for (name, value) in variables {
+        output = output.replace( value.as_str(), &format!( "${{{}}}", name ) );
+    }
+
+    output
+}
+
+impl Builder {
     pub fn run( &self ) -> Result< CargoResult, Error > {
-        let result = self.0.build( Some( |path: &Path| {
-            if let Some( artifact ) = wasm::process_wasm_file( &self.0, path ) {
-                vec![ artifact ]
-            } else {
-                Vec::new()
+        self.run_collecting_errors( &mut Vec::new() )
+    }
+
+    /// Makes the build only type-check the code; nothing gets
+    /// generated so none of the `.wasm` processing will happen.
+    pub fn check_only( mut self ) -> Self {
+        self.build_config.check_only = true;
+        self
+    }
+
+    /// The kind of JavaScript runtime the `.wasm` file gets generated with.
+    pub fn runtime( &self ) -> RuntimeKind {
+        self.wasm_options.runtime
+    }
+
+    /// A hash of everything this build depends on besides the source
+    /// code, e.g. for use as a key by an external build cache.
+    pub fn fingerprint( &self ) -> String {
+        let build = &self.build_config;
+        let mut features = build.features.clone();
+        features.sort();
+
+        // Paths are specific to the machine the crate is being built on, so
+        // they're left out; only the names of the environment variables are
+        // included since the Emscripten SDK they point to is pinned anyway.
+        let environment: Vec< &str > = build.extra_environment.iter().map( |&(ref key, _)| key.as_str() ).collect();
+        let rustflags: Vec< String > = build.extra_rustflags.iter().map( |flag| unexpand_variables( flag, &self.expanded_variables ) ).collect();
+        let inputs = json!({
+            "cargo-web": env!( "CARGO_PKG_VERSION" ),
+            "rustc": rustc_version(),
+            "triplet": build.triplet,
+            "target": format!( "{:?}", build.build_target ),
+            "build-type": format!( "{:?}", build.build_type ),
+            "features": features,
+            "no-default-features": build.no_default_features,
+            "all-features": build.enable_all_features,
+            "rustflags": rustflags,
+            "environment": environment,
+            "cargo-args": fingerprinted_cargo_args( &build.extra_cargo_args ),
+            "emit": build.emitted_extensions,
+            "build-info": self.wasm_options.build_info.as_ref().map( |build_info| build_info.version.clone() ),
+            "wasm-opt-args": self.wasm_options.wasm_opt_args,
+            "wasm-opt-level": self.wasm_options.wasm_opt_level,
+            "keep-debug": self.wasm_options.keep_debug,
+            "normalize": self.wasm_options.normalize,
+            "skip-js": self.wasm_options.skip_js,
+            "typescript": self.wasm_options.typescript,
+            "runtime": format!( "{:?}", self.wasm_options.runtime ),
+            "module-name": self.wasm_options.module_name,
+            "generate-html": self.wasm_options.generate_html,
+            "content-security-policy": self.wasm_options.content_security_policy,
+            "sign-command": self.wasm_options.sign_command
+        });
+
+        // The keys of JSON objects are always sorted, so this is canonical.
+        let mut hasher = sha2::Sha256::default();
+        hasher.input( inputs.to_string().as_bytes() );
+        hasher.result().map( |byte| format!( "{:02x}", byte ) ).join( "" )
+    }
+
+    /// Like `run`, but if the build fails the compiler's
+    /// errors are also appended to `errors`.
+    pub fn run_collecting_errors( &self, errors: &mut Vec< String > ) -> Result< CargoResult, Error > {
+        if self.print_build_config {
+            print_build_config( &self.build_config );
+        }
+
+        // Only once per invocation, e.g. when building the whole workspace,
+        // and only when something is actually going to be linked.
+        static CLEARED_EMSCRIPTEN_CACHE: AtomicBool = ATOMIC_BOOL_INIT;
+        if self.clear_emscripten_cache && !self.build_config.check_only && !CLEARED_EMSCRIPTEN_CACHE.swap( true, Ordering::SeqCst ) {
+            clear_emscripten_cache( false )?;
+        }
+
+        // The heartbeat thread stops as soon as its sender is dropped.
+        let heartbeat = self.heartbeat_interval.map( spawn_heartbeat );
+        let result = self.build_config.build( Some( |path: &Path| {
+            if self.build_config.check_only {
+                return Vec::new();
+            }
+
+            let mut artifacts = wasm::process_wasm_file( &self.build_config, &self.wasm_options, path );
+
+            // This has to run after the `.wasm` file was processed since that modifies it.
+            if let Some( ref command ) = self.wasm_options.sign_command {
+                artifacts.extend( wasm::sign_artifact( path, command ) );
             }
+
+            artifacts
         }));
+        drop( heartbeat );
+
+        // This is done before checking the status since the report
+        // is most useful when the build has failed.
+        if let Some( ref sarif_log ) = self.sarif_log {
+            sarif_log.update( format!( "{:?}", self.build_config.build_target ), result.sarif_results() )?;
+        }
 
         if result.is_ok() == false {
+            errors.extend( result.error_messages().iter().cloned() );
             return Err( Error::BuildError );
         }
 
+        // These have to look at every `.wasm` file, even the ones `--emit` left out.
+        let wasm_artifacts: Vec< &PathBuf > = result.all_artifacts().iter()
+            .filter( |artifact| artifact.extension().map( |ext| ext == "wasm" ).unwrap_or( false ) )
+            .collect();
+
+        if let Some( ref allowed_imports ) = self.wasm_options.allowed_imports {
+            for artifact in &wasm_artifacts {
+                let disallowed = wasm::find_disallowed_imports( artifact, allowed_imports );
+                if !disallowed.is_empty() {
+                    println_err!( "error: {:?} has imports which aren't allowed by `allowed-imports` in your `Web.toml`:", artifact.file_name().unwrap() );
+                    for import in disallowed {
+                        println_err_cont!( "    {}", import );
+                    }
+
+                    return Err( Error::BuildError );
+                }
+            }
+        }
+
+        if let Some( ref expected_features ) = self.expected_features {
+            let unexpected = find_unexpected_features( expected_features, result.activated_features() );
+            if !unexpected.is_empty() {
+                println_err!( "error: the following features were activated even though `[expected-features]` in your `Web.toml` doesn't list them:" );
+                for (name, feature) in unexpected {
+                    println_err_cont!( "    {}: {}", name, feature );
+                }
+
+                return Err( Error::BuildError );
+            }
+        }
+
+        if self.analyze_deps {
+            for artifact in &wasm_artifacts {
+                wasm_analyze_deps::print_code_size_by_crate( artifact );
+            }
+        }
+
+        if self.print_wasm_size {
+            let as_json = self.build_config.message_format == MessageFormat::Json;
+            for artifact in &wasm_artifacts {
+                wasm_size::print_wasm_size( artifact, as_json );
+            }
+        }
+
         Ok( result )
     }
 }
+
+#[test]
+fn test_split_features() {
+    assert_eq!( split_features( "foo bar" ), vec![ "foo", "bar" ] );
+    assert_eq!( split_features( "foo,bar" ), vec![ "foo", "bar" ] );
+    assert_eq!( split_features( "foo, bar" ), vec![ "foo", "bar" ] );
+    assert_eq!( split_features( "foo,bar," ), vec![ "foo", "bar" ] );
+    assert_eq!( split_features( "foo bar foo" ), vec![ "foo", "bar" ] );
+}
+
+#[test]
+fn test_undeclared_features() {
+    let declared = vec![ "default".to_owned(), "serde".to_owned() ];
+    assert_eq!( undeclared_features( &[ "serde", "sered", "stdweb/experimental_features" ], &declared ), vec![ "sered" ] );
+}
+
+#[test]
+fn test_find_unexpected_features() {
+    let mut expected = BTreeMap::new();
+    expected.insert( "serde".to_owned(), vec![ "std".to_owned() ] );
+
+    let activated = vec![
+        ("serde".to_owned(), vec![ "std".to_owned(), "derive".to_owned() ]),
+        ("serde".to_owned(), vec![ "derive".to_owned() ]),
+        ("libc".to_owned(), vec![ "extra_traits".to_owned() ])
+    ];
+
+    assert_eq!( find_unexpected_features( &expected, &activated ), vec![ ("serde".to_owned(), "derive".to_owned()) ] );
+}
+
+#[test]
+fn test_check_target_flags() {
+    let project = CargoProject {
+        packages: Vec::new(),
+        workspace_root: PathBuf::new()
+    };
+
+    let check = |args: &[&str]| {
+        let app = ::add_shared_build_params( clap::SubCommand::with_name( "build" ) );
+        let matches = app.get_matches_from_safe( args ).unwrap();
+        let matcher = BuildArgsMatcher {
+            matches: &matches,
+            project: &project,
+            config: Config::default(),
+            sarif_log: None,
+            force_release: false
+        };
+
+        matcher.check_target_flags()
+    };
+
+    assert!( check( &[ "build" ] ).is_ok() );
+    assert!( check( &[ "build", "--target-webasm" ] ).is_ok() );
+    match check( &[ "build", "--target-webasm", "--target-webasm-emscripten" ] ) {
+        Err( Error::ConfigurationError( message ) ) => {
+            assert!( message.contains( "`--target-webasm`" ) );
+            assert!( message.contains( "`--target-webasm-emscripten`" ) );
+        },
+        _ => panic!( "expected a configuration error" )
+    }
+    assert!( check( &[ "build", "--target-asmjs-emscripten", "--target-webasm" ] ).is_err() );
+}
+
+#[test]
+fn test_fingerprint_ignores_machine_specific_args() {
+    let builder = |cargo_args: &[&str], rustflags: &[&str], prefix: &str| {
+        let mut expanded_variables = BTreeMap::new();
+        expanded_variables.insert( "PREFIX".to_owned(), prefix.to_owned() );
+
+        Builder {
+            build_config: BuildConfig {
+                build_target: ::cargo_shim::BuildTarget::Bin( "app".to_owned(), Profile::Main ),
+                build_type: BuildType::Release,
+                triplet: Some( "wasm32-unknown-unknown".to_owned() ),
+                package: Some( "app".to_owned() ),
+                features: Vec::new(),
+                no_default_features: false,
+                enable_all_features: false,
+                extra_paths: Vec::new(),
+                extra_rustflags: rustflags.iter().map( |flag| flag.to_string() ).collect(),
+                extra_environment: Vec::new(),
+                extra_cargo_args: cargo_args.iter().map( |arg| arg.to_string() ).collect(),
+                message_format: MessageFormat::Human,
+                relative_paths_root: None,
+                emitted_extensions: None,
+                use_color: false,
+                is_verbose: false,
+                check_only: false
+            },
+            wasm_options: ProcessOptions::default(),
+            clear_emscripten_cache: false,
+            heartbeat_interval: None,
+            expected_features: None,
+            sarif_log: None,
+            analyze_deps: false,
+            print_wasm_size: false,
+            print_build_config: false,
+            expanded_variables
+        }.fingerprint()
+    };
+
+    let fingerprint = builder( &[ "--locked" ], &[ "-C", "link-arg=-L/opt/web/lib" ], "/opt/web" );
+    assert_eq!( builder( &[ "--locked", "--target-dir", "/tmp/target", "--jobs", "4" ], &[ "-C", "link-arg=-L/opt/web/lib" ], "/opt/web" ), fingerprint );
+    assert_eq!( builder( &[ "--jobs", "2", "--locked", "--target-dir", "/home/user/target" ], &[ "-C", "link-arg=-L/usr/local/lib" ], "/usr/local" ), fingerprint );
+    assert_ne!( builder( &[ "--frozen" ], &[ "-C", "link-arg=-L/opt/web/lib" ], "/opt/web" ), fingerprint );
+}
+
+#[test]
+fn test_heartbeat_interval() {
+    let project = CargoProject {
+        packages: Vec::new(),
+        workspace_root: PathBuf::new()
+    };
+
+    let interval = |args: &[&str]| {
+        // Only some of the subcommands accept `--message-format`.
+        let app = ::add_shared_build_params( clap::SubCommand::with_name( "build" ) )
+            .arg( clap::Arg::with_name( "message-format" ).long( "message-format" ).takes_value( true ) );
+        let matches = app.get_matches_from_safe( args ).map_err( |_| () )?;
+        let matcher = BuildArgsMatcher {
+            matches: &matches,
+            project: &project,
+            config: Config::default(),
+            sarif_log: None,
+            force_release: false
+        };
+
+        Ok::< _, () >( matcher.heartbeat_interval() )
+    };
+
+    assert_eq!( interval( &[ "build", "--build-heartbeat", "30" ] ), Ok( Some( Duration::from_secs( 30 ) ) ) );
+    assert_eq!( interval( &[ "build", "--build-heartbeat", "0" ] ), Ok( None ) );
+    assert_eq!( interval( &[ "build", "--build-heartbeat", "30", "--message-format", "json" ] ), Ok( None ) );
+    assert!( interval( &[ "build", "--build-heartbeat", "soon" ] ).is_err() );
+}
@@ -195,7 +195,7 @@ fn print_diagnostic< W: Write >( use_color: bool, diag: &Diagnostic, fp: &mut W
     Ok(())
 }
 
-pub fn print( message: &Message ) {
+pub fn print( message: &Message, use_color: bool ) {
     let diag = &message.message;
 
     // Here we get the human readable message from rustc;
@@ -241,7 +241,7 @@ pub fn print( message: &Message ) {
     // colorized messages out of rustc when using the JSON
     // message format.
     let mut output = String::new();
-    print_diagnostic( true, diag, &mut output ).unwrap();
+    print_diagnostic( use_color, diag, &mut output ).unwrap();
     eprint!( "{}", output );
 }
 
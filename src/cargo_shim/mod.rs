@@ -11,9 +11,12 @@ use serde_json;
 mod cargo_output;
 mod rustc_diagnostic;
 mod diagnostic_formatter;
+mod sarif;
 
 use self::cargo_output::CargoOutput;
 
+pub use self::sarif::report as sarif_report;
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum BuildType {
     Debug,
@@ -38,15 +41,22 @@ pub enum TargetKind {
 
 #[derive(Clone, Debug)]
 pub struct CargoProject {
-    pub packages: Vec< CargoPackage >
+    pub packages: Vec< CargoPackage >,
+    pub workspace_root: PathBuf
 }
 
 #[derive(Clone, Debug)]
 pub struct CargoPackage {
     pub name: String,
+    pub version: String,
     pub manifest_path: PathBuf,
     pub crate_root: PathBuf,
-    pub targets: Vec< CargoTarget >
+    pub targets: Vec< CargoTarget >,
+    // Every feature which can be enabled, including the implicit
+    // ones which optional dependencies get.
+    pub features: Vec< String >,
+    /// Whether it's one of the workspace's members instead of a dependency.
+    pub is_workspace_member: bool
 }
 
 #[derive(Clone, Debug)]
@@ -56,16 +66,57 @@ pub struct CargoTarget {
     pub source_directory: PathBuf
 }
 
+// Our version of `cargo_metadata` doesn't tell us where the workspace
+// root is, so we approximate it with the closest common ancestor
+// of all of the workspace members.
+fn common_ancestor( paths: &[PathBuf] ) -> PathBuf {
+    let mut ancestor = match paths.first() {
+        Some( path ) => path.clone(),
+        None => return PathBuf::new()
+    };
+
+    for path in &paths[ 1.. ] {
+        while !path.starts_with( &ancestor ) {
+            if !ancestor.pop() {
+                break;
+            }
+        }
+    }
+
+    ancestor
+}
+
 impl CargoProject {
     pub fn new( manifest_path: Option< &str > ) -> CargoProject {
         let metadata = cargo_metadata::metadata( manifest_path.map( |path| Path::new( path ) ) ).unwrap();
+        let workspace_members = metadata.workspace_members;
+        let is_workspace_member = |package: &cargo_metadata::Package| {
+            workspace_members.iter().any( |member| member.name == package.name && member.version.to_string() == package.version )
+        };
+
+        let member_roots: Vec< PathBuf > = metadata.packages.iter()
+            .filter( |package| is_workspace_member( package ) )
+            .map( |package| Path::new( &package.manifest_path ).parent().unwrap().to_owned() )
+            .collect();
+
         CargoProject {
+            workspace_root: common_ancestor( &member_roots ),
             packages: metadata.packages.into_iter().map( |package| {
+                let is_workspace_member = is_workspace_member( &package );
                 let manifest_path: PathBuf = package.manifest_path.into();
+                let mut features: Vec< String > = package.features.keys().cloned()
+                    .chain( package.dependencies.iter().filter( |dependency| dependency.optional ).map( |dependency| dependency.name.clone() ) )
+                    .collect();
+                features.sort();
+                features.dedup();
+
                 CargoPackage {
                     name: package.name,
+                    version: package.version,
                     crate_root: manifest_path.parent().unwrap().into(),
                     manifest_path: manifest_path,
+                    features,
+                    is_workspace_member,
                     targets: package.targets.into_iter().filter_map( |target| {
                         Some( CargoTarget {
                             name: target.name,
@@ -119,8 +170,16 @@ pub struct BuildConfig {
     pub extra_paths: Vec< PathBuf >,
     pub extra_rustflags: Vec< String >,
     pub extra_environment: Vec< (String, String) >,
+    pub extra_cargo_args: Vec< String >,
     pub message_format: MessageFormat,
-    pub is_verbose: bool
+    pub relative_paths_root: Option< PathBuf >,
+    /// When set only the artifacts with these extensions are reported.
+    pub emitted_extensions: Option< Vec< String > >,
+    /// Whether the compiler's messages should be colored.
+    pub use_color: bool,
+    pub is_verbose: bool,
+    /// Runs `cargo check` instead, so only metadata is produced.
+    pub check_only: bool
 }
 
 fn profile_to_arg( profile: Profile ) -> &'static str {
@@ -141,14 +200,58 @@ pub fn target_to_build_target( target: &CargoTarget, profile: Profile ) -> Build
     }
 }
 
+fn make_paths_relative( value: &mut serde_json::Value, root: &Path ) {
+    match *value {
+        serde_json::Value::String( ref mut string ) => {
+            let relative = Path::new( string.as_str() ).strip_prefix( root ).ok().map( |path| path.to_string_lossy().into_owned() );
+            if let Some( relative ) = relative {
+                *string = relative;
+            }
+        },
+        serde_json::Value::Array( ref mut array ) => {
+            for value in array {
+                make_paths_relative( value, root );
+            }
+        },
+        serde_json::Value::Object( ref mut object ) => {
+            for (_, value) in object.iter_mut() {
+                make_paths_relative( value, root );
+            }
+        },
+        _ => {}
+    }
+}
+
+/// Appends our own flags to the ones from the user's `RUSTFLAGS`, so that
+/// they're kept; the user's flags come first, followed by cargo-web's.
+fn merge_rustflags( user_rustflags: Option< OsString >, extra_rustflags: &[String] ) -> OsString {
+    let mut rustflags = user_rustflags.unwrap_or( OsString::new() );
+    for flag in extra_rustflags {
+        if !rustflags.is_empty() {
+            rustflags.push( " " );
+        }
+        rustflags.push( flag );
+    }
+
+    rustflags
+}
+
 impl BuildConfig {
+    fn print_json( &self, mut value: serde_json::Value ) {
+        if let Some( ref root ) = self.relative_paths_root {
+            make_paths_relative( &mut value, root );
+        }
+
+        println!( "{}", serde_json::to_string( &value ).unwrap() );
+    }
+
     fn as_command( &self ) -> Command {
         let mut command = Command::new( "cargo" );
-        command.arg( "rustc" );
+        command.arg( if self.check_only { "check" } else { "rustc" } );
         command.arg( "--message-format" );
         command.arg( "json" );
         command.arg( "--color" );
-        command.arg( "always" );
+        command.arg( if self.use_color { "always" } else { "never" } );
 
         if let Some( ref triplet ) = self.triplet {
             command.arg( "--target" ).arg( triplet.as_str() );
@@ -165,16 +268,21 @@ impl BuildConfig {
             }
         }
 
+        // `cargo check` only knows about the `test` profile.
+        let profile_args = |command: &mut Command, profile: Profile| {
+            if !self.check_only || profile == Profile::Test {
+                command.arg( "--profile" ).arg( profile_to_arg( profile ) );
+            }
+        };
+
         match self.build_target {
             BuildTarget::Lib( _, profile ) => {
-                command
-                    .arg( "--profile" ).arg( profile_to_arg( profile ) )
-                    .arg( "--lib" );
+                profile_args( &mut command, profile );
+                command.arg( "--lib" );
             },
             BuildTarget::Bin( ref name, profile ) => {
-                command
-                    .arg( "--profile" ).arg( profile_to_arg( profile ) )
-                    .arg( "--bin" ).arg( name.as_str() );
+                profile_args( &mut command, profile );
+                command.arg( "--bin" ).arg( name.as_str() );
             },
             BuildTarget::ExampleBin( ref name ) => {
                 command.arg( "--example" ).arg( name.as_str() );
@@ -204,6 +312,7 @@ impl BuildConfig {
             command.arg( "--verbose" );
         }
 
+        command.args( &self.extra_cargo_args );
         command
     }
 
@@ -221,7 +330,7 @@ impl BuildConfig {
             triplet == "wasm32-unknown-emscripten" || triplet == "asmjs-unknown-emscripten"
         }).unwrap_or( false );
 
-        if is_emscripten {
+        if is_emscripten && !self.check_only {
             let no_js_generated = result
                 .artifacts()
                 .iter()
@@ -254,13 +363,7 @@ impl BuildConfig {
         debug!( "Will launch cargo with PATH: {:?}", new_paths );
         command.env( "PATH", new_paths );
 
-        let mut rustflags = env::var_os( "RUSTFLAGS" ).unwrap_or( OsString::new() );
-        for flag in &self.extra_rustflags {
-            if !rustflags.is_empty() {
-                rustflags.push( " " );
-            }
-            rustflags.push( flag );
-        }
+        let rustflags = merge_rustflags( env::var_os( "RUSTFLAGS" ), &self.extra_rustflags );
         debug!( "Will launch cargo with RUSTFLAGS: {:?}", rustflags );
         command.env( "RUSTFLAGS", rustflags );
 
@@ -278,7 +381,11 @@ impl BuildConfig {
             Err( _ ) => {
                 return CargoResult {
                     status: None,
-                    artifacts: Vec::new()
+                    artifacts: Vec::new(),
+                    all_artifacts: Vec::new(),
+                    activated_features: Vec::new(),
+                    sarif_results: Vec::new(),
+                    error_messages: Vec::new()
                 };
             }
         };
@@ -311,6 +418,8 @@ impl BuildConfig {
         });
 
         let mut artifacts = Vec::new();
+        let mut sarif_results = Vec::new();
+        let mut error_messages = Vec::new();
         for line in stdout.lines() {
             let line = match line {
                 Ok( line ) => line,
@@ -328,11 +437,16 @@ impl BuildConfig {
                 match output {
                     CargoOutput::Message( message ) => {
                         match self.message_format {
-                            MessageFormat::Human => diagnostic_formatter::print( &message ),
+                            MessageFormat::Human => diagnostic_formatter::print( &message, self.use_color ),
                             MessageFormat::Json => {
-                                println!( "{}", serde_json::to_string( &message.to_json_value() ).unwrap() );
+                                self.print_json( message.to_json_value() );
                             }
                         }
+
+                        sarif_results.extend( sarif::to_result( &message.message ) );
+                        if message.message.level.starts_with( "error" ) {
+                            error_messages.extend( message.message.rendered.clone() );
+                        }
                     },
                     CargoOutput::Artifact( artifact ) => {
                         for filename in &artifact.filenames {
@@ -345,7 +459,7 @@ impl BuildConfig {
                         match self.message_format {
                             MessageFormat::Human => {},
                             MessageFormat::Json => {
-                                println!( "{}", serde_json::to_string( &executed.to_json_value() ).unwrap() );
+                                self.print_json( executed.to_json_value() );
                             }
                         }
                     }
@@ -394,7 +508,11 @@ impl BuildConfig {
         }
 
         let mut artifact_paths = Vec::new();
+        let mut all_artifact_paths = Vec::new();
+        let mut activated_features = Vec::new();
         for mut artifact in artifacts {
+            activated_features.push( (artifact.package_id.name.clone(), artifact.features.clone()) );
+
             if let Some( ref mut callback ) = extra_artifact_generator.as_mut() {
                 let mut extra_filenames = Vec::new();
                 for filename in &artifact.filenames {
@@ -405,10 +523,17 @@ impl BuildConfig {
                 artifact.filenames.extend( extra_filenames );
             }
 
+            all_artifact_paths.extend( artifact.filenames.iter().map( PathBuf::from ) );
+            if let Some( ref extensions ) = self.emitted_extensions {
+                artifact.filenames.retain( |filename| {
+                    Path::new( filename ).extension().map( |ext| extensions.iter().any( |extension| ext == extension.as_str() ) ).unwrap_or( false )
+                });
+            }
+
             match self.message_format {
                 MessageFormat::Human => {},
                 MessageFormat::Json => {
-                    println!( "{}", serde_json::to_string( &artifact.to_json_value() ).unwrap() );
+                    self.print_json( artifact.to_json_value() );
                 }
             }
 
@@ -421,14 +546,22 @@ impl BuildConfig {
 
         CargoResult {
             status: Some( status ),
-            artifacts: artifact_paths
+            artifacts: artifact_paths,
+            all_artifacts: all_artifact_paths,
+            activated_features,
+            sarif_results,
+            error_messages
         }
     }
 }
 
 pub struct CargoResult {
     status: Option< i32 >,
-    artifacts: Vec< PathBuf >
+    artifacts: Vec< PathBuf >,
+    all_artifacts: Vec< PathBuf >,
+    activated_features: Vec< (String, Vec< String >) >,
+    sarif_results: Vec< serde_json::Value >,
+    error_messages: Vec< String >
 }
 
 impl CargoResult {
@@ -439,4 +572,47 @@ impl CargoResult {
     pub fn artifacts( &self ) -> &[PathBuf] {
         &self.artifacts
     }
+
+    /// Every artifact which was built, including the ones `emitted_extensions` left out.
+    pub fn all_artifacts( &self ) -> &[PathBuf] {
+        &self.all_artifacts
+    }
+
+    /// The features each of the built crates was compiled with, by crate name.
+    pub fn activated_features( &self ) -> &[(String, Vec< String >)] {
+        &self.activated_features
+    }
+
+    /// The compiler's diagnostics converted into SARIF results.
+    pub fn sarif_results( &self ) -> &[serde_json::Value] {
+        &self.sarif_results
+    }
+
+    /// The compiler's errors, as rustc would render them.
+    pub fn error_messages( &self ) -> &[String] {
+        &self.error_messages
+    }
+}
+
+#[test]
+fn test_make_paths_relative() {
+    let mut value = json!({
+        "src_path": "/ws/src/main.rs",
+        "spans": [ { "file_name": "src/lib.rs" } ],
+        "message": "/ws is not a path prefix here"
+    });
+
+    make_paths_relative( &mut value, Path::new( "/ws" ) );
+    assert_eq!( value, json!({
+        "src_path": "src/main.rs",
+        "spans": [ { "file_name": "src/lib.rs" } ],
+        "message": "/ws is not a path prefix here"
+    }));
+}
+
+#[test]
+fn test_merge_rustflags() {
+    let extra = vec![ "-C".to_owned(), "link-arg=-s".to_owned() ];
+    assert_eq!( merge_rustflags( Some( "-C target-feature=+simd128".into() ), &extra ), OsString::from( "-C target-feature=+simd128 -C link-arg=-s" ) );
+    assert_eq!( merge_rustflags( None, &extra ), OsString::from( "-C link-arg=-s" ) );
 }
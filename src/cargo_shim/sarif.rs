@@ -0,0 +1,98 @@
+use serde_json::Value;
+
+use cargo_shim::rustc_diagnostic::Diagnostic;
+
+fn level( diagnostic: &Diagnostic ) -> &'static str {
+    if diagnostic.level.starts_with( "error" ) {
+        "error"
+    } else if diagnostic.level == "warning" {
+        "warning"
+    } else {
+        "note"
+    }
+}
+
+// Diagnostics without a location (e.g. "aborting due to previous error")
+// aren't useful to code scanning tools, so they are skipped.
+pub fn to_result( diagnostic: &Diagnostic ) -> Option< Value > {
+    let locations: Vec< Value > = diagnostic.spans.iter().filter( |span| span.is_primary ).map( |span| {
+        json!({
+            "physicalLocation": {
+                "artifactLocation": {
+                    "uri": span.file_name
+                },
+                "region": {
+                    "startLine": span.line_start,
+                    "startColumn": span.column_start,
+                    "endLine": span.line_end,
+                    "endColumn": span.column_end
+                }
+            }
+        })
+    }).collect();
+
+    if locations.is_empty() {
+        return None;
+    }
+
+    let mut result = json!({
+        "level": level( diagnostic ),
+        "message": {
+            "text": diagnostic.message
+        },
+        "locations": locations
+    });
+
+    if let Some( ref code ) = diagnostic.code {
+        result[ "ruleId" ] = code.code.clone().into();
+    }
+
+    Some( result )
+}
+
+pub fn report( results: Vec< Value > ) -> Value {
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "cargo-web",
+                    "version": env!( "CARGO_PKG_VERSION" ),
+                    "informationUri": "https://github.com/koute/cargo-web"
+                }
+            },
+            "results": results
+        }]
+    })
+}
+
+#[test]
+fn test_to_result() {
+    let diagnostic: Diagnostic = ::serde_json::from_value( json!({
+        "message": "unused variable: `x`",
+        "code": { "code": "unused_variables", "explanation": null },
+        "level": "warning",
+        "spans": [{
+            "file_name": "src/main.rs",
+            "byte_start": 20,
+            "byte_end": 21,
+            "line_start": 2,
+            "line_end": 2,
+            "column_start": 9,
+            "column_end": 10,
+            "is_primary": true,
+            "text": [],
+            "label": null,
+            "suggested_replacement": null,
+            "expansion": null
+        }],
+        "children": [],
+        "rendered": null
+    })).unwrap();
+
+    let result = to_result( &diagnostic ).unwrap();
+    assert_eq!( result[ "level" ], "warning" );
+    assert_eq!( result[ "ruleId" ], "unused_variables" );
+    assert_eq!( result[ "locations" ][ 0 ][ "physicalLocation" ][ "region" ][ "startLine" ], 2 );
+}
@@ -0,0 +1,65 @@
+use std::process::exit;
+use std::ffi::OsStr;
+use std::iter;
+
+use clap;
+
+use cargo_shim::{
+    Profile,
+    CargoProject,
+    TargetKind
+};
+
+use build::BuildArgsMatcher;
+use error::Error;
+use cmd_test::test_in_nodejs;
+use test_chromium::{DEFAULT_CHROMIUM_LAUNCH_RETRIES, test_in_chromium};
+
+pub fn command_bench< 'a >( matches: &clap::ArgMatches< 'a >, project: &CargoProject ) -> Result< (), Error > {
+    let build_matcher = BuildArgsMatcher::new( matches, project )?;
+
+    // The harness needs a clock, and `std` doesn't have one there.
+    if build_matcher.targeting_native_wasm() {
+        return Err( Error::ConfigurationError( "running benchmarks is currently not supported on the native wasm target".into() ) );
+    }
+
+    let use_nodejs = matches.is_present( "nodejs" );
+    let no_run = matches.is_present( "no-run" );
+    let arg_passthrough: Vec< &OsStr > = iter::once( OsStr::new( "--bench" ) )
+        .chain( matches.values_of_os( "passthrough" ).into_iter().flat_map( |args| args ) )
+        .collect();
+
+    let package = build_matcher.package_or_default()?;
+    let config = build_matcher.config();
+    let targets = build_matcher.target_or_select( package, |target| {
+        target.kind == TargetKind::Lib || target.kind == TargetKind::Bin || target.kind == TargetKind::Bench
+    })?;
+
+    let mut builds = Vec::new();
+    for target in targets {
+        let builder = build_matcher.prepare_builder( config, package, target, Profile::Bench )?;
+        builds.push( builder.run()? );
+    }
+
+    if no_run {
+        exit( 0 );
+    }
+
+    let launch_retries = matches.value_of( "chromium-launch-retries" ).map( |value| value.parse().unwrap() )
+        .or_else( || config.test.as_ref().and_then( |test| test.chromium_launch_retries ) )
+        .unwrap_or( DEFAULT_CHROMIUM_LAUNCH_RETRIES );
+    let mut failure_code = None;
+    for build in builds {
+        if use_nodejs {
+            test_in_nodejs( &build_matcher, build, &arg_passthrough, None, &mut failure_code )?;
+        } else {
+            test_in_chromium( &build_matcher, build, &arg_passthrough, launch_retries, None, &mut failure_code )?;
+        }
+    }
+
+    if let Some( code ) = failure_code {
+        exit( code );
+    }
+
+    Ok(())
+}
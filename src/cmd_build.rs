@@ -1,30 +1,136 @@
+use std::path::Path;
+use std::fs;
+use std::env;
+
 use clap;
+use serde_json::{self, Value};
 
 use cargo_shim::{
     Profile,
     CargoProject,
+    CargoPackage,
+    CargoTarget,
+    CargoResult,
     TargetKind
 };
 
 use build::BuildArgsMatcher;
 use config::Config;
 use error::Error;
+use utils::write;
 
-pub fn command_build< 'a >( matches: &clap::ArgMatches< 'a >, project: &CargoProject ) -> Result< (), Error > {
-    let build_matcher = BuildArgsMatcher {
-        matches: matches,
-        project: project
-    };
+pub fn copy_artifacts( result: &CargoResult, out_dir: &Path ) -> Result< (), Error > {
+    fs::create_dir_all( out_dir )
+        .map_err( |err| Error::ConfigurationError( format!( "cannot create the output directory {:?}: {}", out_dir, err ) ) )?;
 
-    let package = build_matcher.package_or_default()?;
-    let config = Config::load_for_package_printing_warnings( &package ).unwrap().unwrap_or_default();
-    let targets = build_matcher.target_or_select( package, |target| {
-        target.kind == TargetKind::Lib || target.kind == TargetKind::Bin
-    })?;
+    for artifact in result.artifacts() {
+        let target_path = out_dir.join( artifact.file_name().unwrap() );
+        fs::copy( artifact, &target_path )
+            .map_err( |err| Error::ConfigurationError( format!( "cannot copy {:?} to {:?}: {}", artifact, target_path, err ) ) )?;
+    }
+
+    Ok(())
+}
+
+fn artifact_kind( path: &Path ) -> String {
+    let name = path.file_name().unwrap().to_string_lossy();
+    if name.ends_with( ".d.ts" ) {
+        "dts".to_owned()
+    } else {
+        path.extension().map( |ext| ext.to_string_lossy().into_owned() ).unwrap_or_default()
+    }
+}
 
+fn artifact_json( target_name: &str, triplet: &str, result: &CargoResult ) -> Result< Vec< Value >, Error > {
+    let current_dir = env::current_dir()
+        .map_err( |err| Error::RuntimeError( "cannot get the current directory".into(), err.into() ) )?;
+
+    Ok( result.artifacts().iter().map( |artifact| {
+        json!({
+            "path": current_dir.join( artifact ),
+            "kind": artifact_kind( artifact ),
+            "target": target_name,
+            "triple": triplet
+        })
+    }).collect() )
+}
+
+fn build_targets(
+    matches: &clap::ArgMatches,
+    build_matcher: &BuildArgsMatcher,
+    config: &Config,
+    package: &CargoPackage,
+    targets: &[&CargoTarget],
+    artifacts: &mut Vec< Value >
+) -> Result< (), Error > {
     for target in targets {
-        let builder = build_matcher.prepare_builder( &config, package, target, Profile::Main );
-        builder.run()?;
+        let builder = build_matcher.prepare_builder( config, package, target, Profile::Main )?;
+        if matches.is_present( "print-fingerprint" ) {
+            println!( "{} {}", builder.fingerprint(), target.name );
+            continue;
+        }
+
+        let result = builder.run()?;
+        if let Some( out_dir ) = matches.value_of_os( "out-dir" ) {
+            copy_artifacts( &result, Path::new( out_dir ) )?;
+        }
+
+        artifacts.extend( artifact_json( &target.name, build_matcher.triplet_or_default(), &result )? );
+    }
+
+    Ok(())
+}
+
+// A failed build of one of the members doesn't stop the others from being built.
+fn build_workspace( matches: &clap::ArgMatches, build_matcher: &BuildArgsMatcher, artifacts: &mut Vec< Value > ) -> Result< (), Error > {
+    let mut failed = Vec::new();
+    for package in build_matcher.project.packages.iter().filter( |package| package.is_workspace_member ) {
+        let targets: Vec< _ > = package.targets.iter()
+            .filter( |target| target.kind == TargetKind::Lib || target.kind == TargetKind::Bin )
+            .collect();
+
+        if targets.is_empty() {
+            println_err!( "    Skipping `{}` since it has no library or binary targets", package.name );
+            continue;
+        }
+
+        let member_matcher = build_matcher.for_member( package )?;
+        match build_targets( matches, &member_matcher, member_matcher.config(), package, &targets, artifacts ) {
+            Ok(()) => {},
+            Err( Error::BuildError ) => failed.push( package.name.as_str() ),
+            Err( error ) => return Err( error )
+        }
+    }
+
+    if !failed.is_empty() {
+        println_err!( "error: failed to build the following packages: {}", failed.join( ", " ) );
+        return Err( Error::BuildError );
+    }
+
+    Ok(())
+}
+
+pub fn command_build< 'a >( matches: &clap::ArgMatches< 'a >, project: &CargoProject ) -> Result< (), Error > {
+    let build_matcher = BuildArgsMatcher::new( matches, project )?;
+
+    let mut artifacts = Vec::new();
+    if matches.is_present( "workspace" ) {
+        build_workspace( matches, &build_matcher, &mut artifacts )?;
+    } else {
+        let package = build_matcher.package_or_default()?;
+        let config = build_matcher.config();
+        let targets = build_matcher.target_or_select( package, |target| {
+            target.kind == TargetKind::Lib || target.kind == TargetKind::Bin
+        })?;
+
+        build_targets( matches, &build_matcher, config, package, &targets, &mut artifacts )?;
+    }
+
+    if let Some( path ) = matches.value_of_os( "artifact-json" ) {
+        let manifest = serde_json::to_string_pretty( &json!({ "artifacts": artifacts }) ).unwrap();
+        write( path, &manifest ).map_err( |err| {
+            Error::RuntimeError( format!( "cannot write the artifact list to {:?}", path ), err.into() )
+        })?;
     }
 
     Ok(())
@@ -0,0 +1,27 @@
+use clap;
+
+use cargo_shim::{
+    Profile,
+    CargoProject,
+    TargetKind
+};
+
+use build::BuildArgsMatcher;
+use error::Error;
+
+pub fn command_check< 'a >( matches: &clap::ArgMatches< 'a >, project: &CargoProject ) -> Result< (), Error > {
+    let build_matcher = BuildArgsMatcher::new( matches, project )?;
+
+    let package = build_matcher.package_or_default()?;
+    let config = build_matcher.config();
+    let targets = build_matcher.target_or_select( package, |target| {
+        target.kind == TargetKind::Lib || target.kind == TargetKind::Bin
+    })?;
+
+    for target in targets {
+        let builder = build_matcher.prepare_builder( config, package, target, Profile::Main )?;
+        builder.check_only().run()?;
+    }
+
+    Ok(())
+}
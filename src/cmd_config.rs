@@ -0,0 +1,13 @@
+use clap;
+use serde_json;
+
+use config::Config;
+use error::Error;
+
+pub fn command_config< 'a >( matches: &clap::ArgMatches< 'a > ) -> Result< (), Error > {
+    if matches.is_present( "schema" ) {
+        println!( "{}", serde_json::to_string_pretty( &Config::json_schema() ).unwrap() );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::io;
+use std::fs;
+use std::env;
+
+use clap;
+use serde_json;
+
+use cargo_shim::{
+    Profile,
+    CargoProject,
+    TargetKind
+};
+
+use build::BuildArgsMatcher;
+use cmd_build::copy_artifacts;
+use cmd_start::index_template_path;
+use error::Error;
+use package::hash_file;
+use utils::{read, write};
+use wasm;
+
+/// Written into the output directory so that a later deploy knows it can
+/// replace a directory which was passed through `--output`; it also lists
+/// the hashes of the deployed files for `cargo web verify`.
+pub const DEPLOY_MANIFEST: &'static str = ".cargo-web-deploy";
+
+// The paths are relative to `root` and always use forward slashes.
+fn collect_files( root: &Path, directory: &Path, output: &mut BTreeMap< String, String > ) -> Result< (), Error > {
+    let error = |path: &Path, err: io::Error| Error::RuntimeError( format!( "cannot read {:?}", path ), err.into() );
+    for entry in fs::read_dir( directory ).map_err( |err| error( directory, err ) )? {
+        let path = entry.map_err( |err| error( directory, err ) )?.path();
+        if path.is_dir() {
+            collect_files( root, &path, output )?;
+            continue;
+        }
+
+        let relative_path: Vec< String > = path.strip_prefix( root ).unwrap().components()
+            .map( |component| component.as_os_str().to_string_lossy().into_owned() )
+            .collect();
+
+        let relative_path = relative_path.join( "/" );
+        if relative_path != DEPLOY_MANIFEST {
+            output.insert( relative_path, hash_file( &path ).map_err( |err| error( &path, err ) )? );
+        }
+    }
+
+    Ok(())
+}
+
+fn write_manifest( output_dir: &Path ) -> Result< (), Error > {
+    let mut files = BTreeMap::new();
+    collect_files( output_dir, output_dir, &mut files )?;
+
+    let path = output_dir.join( DEPLOY_MANIFEST );
+    let manifest = serde_json::to_string_pretty( &json!({ "files": files }) ).unwrap();
+    write( &path, &manifest ).map_err( |err| Error::RuntimeError( format!( "cannot write {:?}", path ), err.into() ) )
+}
+
+fn copy_dir( source: &Path, destination: &Path ) -> Result< (), Error > {
+    let error = |path: &Path, err| Error::ConfigurationError( format!( "cannot copy {:?} to {:?}: {}", path, destination, err ) );
+    fs::create_dir_all( destination ).map_err( |err| error( source, err ) )?;
+    for entry in fs::read_dir( source ).map_err( |err| error( source, err ) )? {
+        let path = entry.map_err( |err| error( source, err ) )?.path();
+        let target_path = destination.join( path.file_name().unwrap() );
+        if path.is_dir() {
+            copy_dir( &path, &target_path )?;
+        } else {
+            fs::copy( &path, &target_path ).map_err( |err| error( &path, err ) )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn can_replace_output_dir( path: &Path ) -> bool {
+    if path.join( DEPLOY_MANIFEST ).is_file() {
+        return true;
+    }
+
+    match fs::read_dir( path ) {
+        Ok( mut entries ) => entries.next().is_none(),
+        // Either it doesn't exist yet, or it's not a directory
+        // in which case creating it will fail with a proper error.
+        Err( _ ) => !path.is_dir()
+    }
+}
+
+// The page's own scripts aren't known here, so a policy
+// which blocks them has to be adjusted by the user.
+fn with_content_security_policy( html: String, policy: Option< &str > ) -> String {
+    let policy = match policy {
+        Some( policy ) => policy,
+        None => return html
+    };
+
+    match wasm::with_content_security_policy( &html, policy ) {
+        Some( html ) => html,
+        None => {
+            println_err!( "warning: the `index.html` has no `<head>`, so `content-security-policy` from your `Web.toml` can't be added to it" );
+            html
+        }
+    }
+}
+
+fn default_output_dir( matches: &clap::ArgMatches, project: &CargoProject ) -> PathBuf {
+    let target_dir = matches.value_of_os( "target-dir" ).map( PathBuf::from )
+        .or_else( || env::var_os( "CARGO_TARGET_DIR" ).map( PathBuf::from ) )
+        .unwrap_or_else( || project.workspace_root.join( "target" ) );
+
+    target_dir.join( "deploy" )
+}
+
+pub fn command_deploy< 'a >( matches: &clap::ArgMatches< 'a >, project: &CargoProject ) -> Result< (), Error > {
+    let mut build_matcher = BuildArgsMatcher::new( matches, project )?;
+    build_matcher.force_release = true;
+
+    let package = build_matcher.package_or_default()?;
+    let config = build_matcher.config();
+    let index_template = index_template_path( config, package )?;
+    let targets = build_matcher.target_or_select( package, |target| target.kind == TargetKind::Bin )?;
+    let target = match targets.first() {
+        Some( target ) => *target,
+        None => return Err( Error::ConfigurationError( "there are no binary targets to deploy".into() ) )
+    };
+
+    // Only our own `target/deploy` can be wiped unconditionally; a directory
+    // from `--output` might as well be the user's own site.
+    let output_dir = match matches.value_of_os( "output" ) {
+        Some( output_dir ) => {
+            let output_dir = PathBuf::from( output_dir );
+            if !can_replace_output_dir( &output_dir ) {
+                return Err( Error::ConfigurationError( format!(
+                    "the output directory {:?} isn't empty and wasn't created by `cargo web deploy`; refusing to overwrite it",
+                    output_dir
+                )));
+            }
+
+            output_dir
+        },
+        None => default_output_dir( matches, project )
+    };
+
+    let builder = build_matcher.prepare_builder( config, package, target, Profile::Main )?;
+    let result = builder.run()?;
+
+    if output_dir.exists() {
+        fs::remove_dir_all( &output_dir )
+            .map_err( |err| Error::ConfigurationError( format!( "cannot remove the old output directory {:?}: {}", output_dir, err ) ) )?;
+    }
+
+    // The same directories `cargo web start` serves the extra files from.
+    let target_static_path = match target.kind {
+        TargetKind::Example => Some( target.source_directory.join( format!( "{}-static", target.name ) ) ),
+        TargetKind::Bin => Some( target.source_directory.join( "static" ) ),
+        _ => None
+    };
+
+    let static_paths = Some( package.crate_root.join( "static" ) ).into_iter().chain( target_static_path );
+    for static_path in static_paths.filter( |path| path.is_dir() ) {
+        copy_dir( &static_path, &output_dir )?;
+    }
+
+    copy_artifacts( &result, &output_dir )?;
+
+    // A configured template takes precedence over an `index.html` from the `static` directories.
+    let policy = config.content_security_policy.as_ref().and_then( |policy| policy.directives() );
+    let index_path = output_dir.join( "index.html" );
+    let html = if index_template.is_some() || !index_path.exists() {
+        let filename = |extension: &str| result.artifacts().iter()
+            .find( |artifact| artifact.extension().map( |ext| ext == extension ).unwrap_or( false ) )
+            .and_then( |artifact| artifact.file_name() )
+            .map( |name| name.to_string_lossy().into_owned() );
+
+        let js_filename = filename( "js" )
+            .ok_or_else( || Error::NoArtifact( "expected the build to produce a `.js` file for the `index.html` to load".into() ) )?;
+
+        match index_template {
+            Some( ref path ) => {
+                let template = read( path ).map_err( |err| Error::RuntimeError( format!( "cannot read {:?}", path ), err.into() ) )?;
+                let html = wasm::render_index_template( &template, &js_filename, filename( "wasm" ).as_ref().map( |name| name.as_str() ) );
+                Some( with_content_security_policy( html, policy ) )
+            },
+            None => Some( wasm::index_html( &js_filename, builder.runtime(), policy ) )
+        }
+    } else if policy.is_some() {
+        let html = read( &index_path ).map_err( |err| Error::RuntimeError( format!( "cannot read {:?}", index_path ), err.into() ) )?;
+        Some( with_content_security_policy( html, policy ) )
+    } else {
+        None
+    };
+
+    if let Some( html ) = html {
+        write( &index_path, &html )
+            .map_err( |err| Error::RuntimeError( format!( "cannot write {:?}", index_path ), err.into() ) )?;
+    }
+
+    write_manifest( &output_dir )?;
+    println_err!( "The application was deployed to {:?}", output_dir );
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use clap;
+
+use emscripten::{
+    known_versions,
+    latest_version,
+    default_version,
+    find_known_version,
+    emscripten_package,
+    binaryen_package,
+    set_default_version,
+    clear_emscripten_cache
+};
+use package::{
+    PrebuiltPackage,
+    download_package,
+    remove_other_versions
+};
+use config::Config;
+use error::Error;
+
+fn unknown_version( version: &str ) -> Error {
+    Error::ConfigurationError( format!(
+        "unknown Emscripten version `{}`; the versions which can be installed are: {}",
+        version,
+        known_versions().join( ", " )
+    ))
+}
+
+fn install( version: &str ) -> Result< Vec< PrebuiltPackage >, Error > {
+    let packages = match (emscripten_package( version ), binaryen_package( version )) {
+        (Some( emscripten ), Some( binaryen )) => vec![ emscripten, binaryen ],
+        _ => return Err( Error::EnvironmentError( "there is no prebuilt Emscripten for your platform; you'll have to use the system one".into() ) )
+    };
+
+    // The checksums are verified while downloading.
+    for package in &packages {
+        download_package( package );
+    }
+
+    Ok( packages )
+}
+
+fn command_update< 'a >( matches: &clap::ArgMatches< 'a > ) -> Result< (), Error > {
+    let version = matches.value_of( "version" ).unwrap_or( latest_version() );
+    if !known_versions().contains( &version ) {
+        return Err( unknown_version( version ) );
+    }
+
+    let packages = install( version )?;
+
+    if matches.is_present( "set-default" ) {
+        set_default_version( version )?;
+        println_err!( "Emscripten {} is now the default", version );
+    }
+
+    if matches.is_present( "remove-previous" ) {
+        for package in &packages {
+            remove_other_versions( package );
+        }
+    }
+
+    Ok(())
+}
+
+// Only the `Web.toml` in the current directory is looked at
+// since this has to work without a crate to build.
+fn pinned_version() -> Result< Option< String >, Error > {
+    let config = Config::load_from_file( "Web.toml" )
+        .map_err( |err| Error::RuntimeError( "cannot load `Web.toml`".into(), err ) )?;
+
+    Ok( config.and_then( |(config, _)| config.emscripten ).and_then( |emscripten| emscripten.version ) )
+}
+
+/// Downloads the Emscripten SDK which a build would use without building anything.
+pub fn command_prepare_emscripten< 'a >( matches: &clap::ArgMatches< 'a > ) -> Result< (), Error > {
+    let requested = match matches.value_of( "version" ) {
+        Some( version ) => Some( version.to_owned() ),
+        None => pinned_version()?
+    };
+
+    let version = match requested {
+        Some( requested ) => find_known_version( &requested ).ok_or_else( || unknown_version( &requested ) )?,
+        None => default_version()
+    };
+
+    install( version )?;
+    println_err!( "Emscripten {} is ready to use", version );
+
+    Ok(())
+}
+
+pub fn command_emscripten< 'a >( matches: &clap::ArgMatches< 'a > ) -> Result< (), Error > {
+    if let Some( matches ) = matches.subcommand_matches( "update" ) {
+        command_update( matches )
+    } else if let Some( matches ) = matches.subcommand_matches( "clear-cache" ) {
+        clear_emscripten_cache( matches.is_present( "global" ) )
+    } else {
+        Err( Error::ConfigurationError( "expected one of the `emscripten` subcommands; see `cargo web emscripten --help`".into() ) )
+    }
+}
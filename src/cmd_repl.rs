@@ -0,0 +1,63 @@
+use std::process::exit;
+
+use clap;
+use serde_json;
+
+use cargo_shim::{
+    Profile,
+    CargoProject,
+    TargetKind
+};
+
+use build::BuildArgsMatcher;
+use error::Error;
+use cmd_test::nodejs_command;
+
+pub fn command_repl< 'a >( matches: &clap::ArgMatches< 'a >, project: &CargoProject ) -> Result< (), Error > {
+    let build_matcher = BuildArgsMatcher::new( matches, project )?;
+
+    if !build_matcher.targeting_native_wasm() {
+        return Err( Error::ConfigurationError( "`cargo web repl` is currently only supported for the native wasm target; please pass `--target-webasm`".into() ) );
+    }
+
+    let mut nodejs = nodejs_command( &build_matcher )?;
+    let package = build_matcher.package_or_default()?;
+    let config = build_matcher.config();
+    let targets = build_matcher.target_or_select( package, |target| {
+        target.kind == TargetKind::Lib
+    })?;
+
+    let target = match targets.first() {
+        Some( target ) => *target,
+        None => return Err( Error::ConfigurationError( "cannot start a REPL for a crate which has no library target!".into() ) )
+    };
+
+    let builder = build_matcher.prepare_builder( config, package, target, Profile::Main )?;
+    let result = builder.run()?;
+    let artifact = match result.artifacts().iter().find( |artifact| artifact.extension().map( |ext| ext == "js" ).unwrap_or( false ) ) {
+        Some( artifact ) => artifact.clone(),
+        None => return Err( Error::ConfigurationError( "no .js artifact was generated; is your library a `cdylib`?".into() ) )
+    };
+
+    // The generated runtime returns the module's exports when it's
+    // `require`d under Node.js, so we just have to put them into
+    // the global scope before the REPL starts.
+    let path = serde_json::to_string( &artifact.to_string_lossy() ).unwrap();
+    let preload = format!( "global.Rust = require( {} ); Object.assign( global, global.Rust );", path );
+
+    println_err!( "The exports of your module are available as globals, and inside of `Rust`." );
+    let status = nodejs
+        .arg( "-i" )
+        .arg( "-e" )
+        .arg( preload )
+        .current_dir( artifact.parent().unwrap() )
+        .status()
+        .map_err( |err| Error::RuntimeError( "cannot launch node.js".into(), err.into() ) )?;
+
+    // E.g. when the module failed to load; Node.js has already printed why.
+    if !status.success() {
+        exit( status.code().unwrap_or( 101 ) );
+    }
+
+    Ok(())
+}
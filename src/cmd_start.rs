@@ -4,7 +4,9 @@ use std::sync::mpsc::channel;
 use std::sync::{Mutex, Arc};
 use std::time::Duration;
 use std::thread;
+use std::process::Command;
 use std::mem;
+use std::iter;
 use std::net::{self, ToSocketAddrs};
 use std::hash::Hash;
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -19,6 +21,7 @@ use notify::{
 use clap;
 use rouille;
 use handlebars::Handlebars;
+use serde_json;
 
 use cargo_shim::{
     Profile,
@@ -35,7 +38,9 @@ use build::{
 };
 use config::Config;
 use error::Error;
+use wasm;
 use utils::{
+    check_if_command_exists,
     read,
     read_bytes
 };
@@ -70,23 +75,87 @@ const DEFAULT_INDEX_HTML: &'static str = r#"
 </html>
 "#;
 
+/// The path of the `index.html` template from `[dev-server]`, if there is one.
+pub fn index_template_path( config: &Config, package: &CargoPackage ) -> Result< Option< PathBuf >, Error > {
+    let path = match config.dev_server.as_ref().and_then( |dev_server| dev_server.index.as_ref() ) {
+        Some( path ) => package.crate_root.join( path ),
+        None => return Ok( None )
+    };
+
+    if !path.is_file() {
+        return Err( Error::ConfigurationError( format!( "the `index` template {:?} from the `[dev-server]` of your `Web.toml` doesn't exist", path ) ) );
+    }
+
+    Ok( Some( path ) )
+}
+
 fn auto_reload_code( hash: u32 ) -> String {
     // TODO: We probably should do this with with Websockets,
     // but it isn't possible when using rouille as a web server. ):
     const TEMPLATE: &'static str = r##"
         window.addEventListener( "load", function() {
             var current_build_hash = {{{current_build_hash}}};
+            var overlay = null;
+            var dismissed_error = null;
+
+            function show_error( error ) {
+                if( error === dismissed_error ) {
+                    return;
+                }
+
+                if( overlay === null ) {
+                    overlay = document.createElement( "div" );
+                    overlay.style.cssText = "position: fixed; top: 0; left: 0; right: 0; bottom: 0; z-index: 2147483647; overflow: auto; padding: 1em; background: rgba( 0, 0, 0, 0.85 ); color: #e8e8e8; font-family: monospace;";
+
+                    var close = document.createElement( "button" );
+                    close.textContent = "\u00d7";
+                    close.title = "Dismiss";
+                    close.style.cssText = "position: fixed; top: 0.5em; right: 0.5em; font-size: 1.5em; background: none; border: none; color: inherit; cursor: pointer;";
+                    close.addEventListener( "click", function() {
+                        dismissed_error = overlay.getAttribute( "data-error" );
+                        hide_error();
+                    });
+
+                    var header = document.createElement( "div" );
+                    header.textContent = "The rebuild has failed:";
+                    header.style.cssText = "color: #ff6b6b; font-weight: bold; margin-bottom: 1em;";
+
+                    var text = document.createElement( "pre" );
+                    text.style.cssText = "white-space: pre-wrap; margin: 0;";
+
+                    overlay.appendChild( close );
+                    overlay.appendChild( header );
+                    overlay.appendChild( text );
+                    document.body.appendChild( overlay );
+                }
+
+                overlay.setAttribute( "data-error", error );
+                overlay.querySelector( "pre" ).textContent = error;
+            }
+
+            function hide_error() {
+                if( overlay !== null ) {
+                    overlay.parentNode.removeChild( overlay );
+                    overlay = null;
+                }
+            }
+
             function try_reload() {
                 var req = new XMLHttpRequest();
                 req.addEventListener( "load" , function() {
-                    if( req.responseText != current_build_hash ) {
+                    var status = JSON.parse( req.responseText );
+                    if( status.error !== null ) {
+                        show_error( status.error );
+                    } else if( status.hash != current_build_hash ) {
                         window.location.reload( true );
+                    } else {
+                        hide_error();
                     }
                 });
                 req.addEventListener( "loadend", function() {
                     setTimeout( try_reload, 500 );
                 });
-                req.open( "GET", "/__cargo-web__/build_hash" );
+                req.open( "GET", "/__cargo-web__/build_status" );
                 req.send();
             }
             try_reload();
@@ -99,6 +168,42 @@ fn auto_reload_code( hash: u32 ) -> String {
     handlebars.template_render( TEMPLATE, &template_data ).unwrap()
 }
 
+// The keys of a `RandomState` are seeded from the operating system's random
+// number generator, so the hashes it produces can't be predicted.
+fn random_nonce() -> String {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut nonce = String::new();
+    for index in 0..2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32( index );
+        nonce.push_str( &format!( "{:016x}", hasher.finish() ) );
+    }
+
+    nonce
+}
+
+fn add_script_nonce( html: &str, nonce: &str ) -> String {
+    let mut output = String::with_capacity( html.len() );
+    let mut rest = html;
+    while let Some( index ) = rest.find( "<script" ) {
+        let (head, tail) = rest.split_at( index + "<script".len() );
+        output.push_str( head );
+
+        let tag_end = tail.find( '>' ).unwrap_or( tail.len() );
+        let is_tag = tail.starts_with( '>' ) || tail.starts_with( char::is_whitespace );
+        if is_tag && !tail[ ..tag_end ].contains( "nonce=" ) {
+            output.push_str( &format!( " nonce=\"{}\"", nonce ) );
+        }
+
+        rest = tail;
+    }
+
+    output.push_str( rest );
+    output
+}
+
 fn hash< T: Hash >( value: T ) -> u64 {
     use std::collections::hash_map::DefaultHasher;
     use std::hash::Hasher;
@@ -111,7 +216,9 @@ fn hash< T: Hash >( value: T ) -> u64 {
 struct LastBuild {
     counter_seed: u64,
     counter: u64,
-    outputs: Vec< Output >
+    outputs: Vec< Output >,
+    /// Why the most recent rebuild failed, if it did.
+    error: Option< String >
 }
 
 impl LastBuild {
@@ -157,10 +264,73 @@ fn result_to_outputs( result: CargoResult ) -> Vec< Output > {
     outputs
 }
 
+fn run_builders( builders: &[Builder], errors: &mut Vec< String > ) -> Result< Vec< Vec< Output > >, Error > {
+    let mut outputs = Vec::new();
+    for builder in builders {
+        outputs.push( result_to_outputs( builder.run_collecting_errors( errors )? ) );
+    }
+
+    Ok( outputs )
+}
+
+fn run_watch_step( crate_root: &Path, key: &str, command: &[String], errors: &mut Vec< String > ) -> Result< (), Error > {
+    if command.is_empty() {
+        return Ok(());
+    }
+
+    println_err!( "    Running `watch.{}`...", key );
+    let error = match Command::new( &command[ 0 ] ).args( &command[ 1.. ] ).current_dir( crate_root ).status() {
+        Ok( ref status ) if status.success() => return Ok(()),
+        Ok( status ) => format!( "`watch.{}` from your `Web.toml` failed with {}", key, status ),
+        Err( error ) => format!( "failed to launch `watch.{}` from your `Web.toml`: {}", key, error )
+    };
+
+    println_err!( "error: {}", error );
+    errors.push( error );
+    Err( Error::BuildError )
+}
+
+struct WatchSteps {
+    crate_root: PathBuf,
+    pre_build: Option< Vec< String > >,
+    post_build: Option< Vec< String > >
+}
+
+impl WatchSteps {
+    fn run( &self, builders: &[Builder], errors: &mut Vec< String > ) -> Result< Vec< Vec< Output > >, Error > {
+        if let Some( ref command ) = self.pre_build {
+            run_watch_step( &self.crate_root, "pre-build", command, errors )?;
+        }
+
+        let outputs = run_builders( builders, errors )?;
+        if let Some( ref command ) = self.post_build {
+            run_watch_step( &self.crate_root, "post-build", command, errors )?;
+        }
+
+        Ok( outputs )
+    }
+}
+
+// This is injected at the end of the `<head>` so that it won't
+// get clobbered by the `__cargo_web` defined in our default page.
+fn spawn_workers_code( worker_scripts: &[(String, String)] ) -> String {
+    let mut code = "(window.__cargo_web = window.__cargo_web || {}).workers = {};".to_owned();
+    for &(ref name, ref script) in worker_scripts {
+        code.push_str( &format!(
+            " __cargo_web.workers[ {} ] = new Worker( {} );",
+            serde_json::to_string( name ).unwrap(),
+            serde_json::to_string( &format!( "/{}", script ) ).unwrap()
+        ));
+    }
+
+    code
+}
+
 fn monitor_for_changes_and_rebuild(
     package: &CargoPackage,
-    target: &CargoTarget,
-    builder: Builder,
+    targets: &[&CargoTarget],
+    steps: WatchSteps,
+    builders: Vec< Builder >,
     last_build: Arc< Mutex< LastBuild > >
 ) -> RecommendedWatcher {
     let (tx, rx) = channel();
@@ -168,27 +338,53 @@ fn monitor_for_changes_and_rebuild(
 
     // TODO: Support local dependencies.
     // TODO: Support Cargo.toml reloading.
-    watcher.watch( &target.source_directory, RecursiveMode::Recursive ).unwrap();
+    for target in targets {
+        watcher.watch( &target.source_directory, RecursiveMode::Recursive ).unwrap();
+    }
     watcher.watch( &package.manifest_path, RecursiveMode::NonRecursive ).unwrap();
+
+    let web_toml = package.manifest_path.with_file_name( "Web.toml" );
+    if web_toml.exists() {
+        watcher.watch( &web_toml, RecursiveMode::NonRecursive ).unwrap();
+    }
+
     thread::spawn( move || {
         let rx = rx;
         while let Ok( event ) = rx.recv() {
-            match event {
-                DebouncedEvent::Create( _ ) |
-                DebouncedEvent::Remove( _ ) |
-                DebouncedEvent::Rename( _, _ ) |
-                DebouncedEvent::Write( _ ) => {},
+            let path = match event {
+                DebouncedEvent::Create( path ) |
+                DebouncedEvent::Remove( path ) |
+                DebouncedEvent::Rename( _, path ) |
+                DebouncedEvent::Write( path ) => path,
                 _ => continue
             };
 
+            // The builders were set up from the old config, so rebuilding wouldn't help.
+            if path == web_toml {
+                println_err!( "warning: your `Web.toml` has changed; restart `cargo web start` for the changes to take effect" );
+                continue;
+            }
+
             println_err!( "==== Triggering `cargo build` ====" );
-            let new_result = builder.run();
-            if let Ok( new_result ) = new_result {
-                let mut new_outputs = result_to_outputs( new_result );
-                let mut last_build = last_build.lock().unwrap();
+            // If any of the steps fail the page isn't reloaded; it keeps
+            // on showing the last successful build along with the errors.
+            let mut errors = Vec::new();
+            let new_outputs = steps.run( &builders, &mut errors );
+            let mut last_build = last_build.lock().unwrap();
+            match new_outputs {
+                Ok( new_outputs ) => {
+                    let mut new_outputs = new_outputs.into_iter().flat_map( |outputs| outputs ).collect();
+                    mem::swap( &mut last_build.outputs, &mut new_outputs );
+                    last_build.counter += 1;
+                    last_build.error = None;
+                },
+                Err( _ ) => {
+                    if errors.is_empty() {
+                        errors.push( "the build failed; see the terminal for details".to_owned() );
+                    }
 
-                mem::swap( &mut last_build.outputs, &mut new_outputs );
-                last_build.counter += 1;
+                    last_build.error = Some( errors.join( "\n" ) );
+                }
             }
         }
     });
@@ -196,24 +392,114 @@ fn monitor_for_changes_and_rebuild(
     watcher
 }
 
+// DWARF debug info references the source files by their absolute
+// paths, so that's how the browser is going to request them; relative
+// paths are resolved against the crate's root. Only files from inside
+// of the crate are ever served.
+fn find_source_file( crate_root: &Path, url: &str ) -> Option< PathBuf > {
+    if !url.ends_with( ".rs" ) {
+        return None;
+    }
+
+    let path = Path::new( url );
+    let path = if path.starts_with( crate_root ) {
+        path.to_owned()
+    } else {
+        crate_root.join( url.trim_left_matches( '/' ) )
+    };
+
+    let path = path.canonicalize().ok()?;
+    if path.starts_with( crate_root ) {
+        Some( path )
+    } else {
+        None
+    }
+}
+
+fn mime_override< 'a >( overrides: &'a BTreeMap< String, String >, path: &str ) -> Option< &'a str > {
+    let extension = Path::new( path ).extension()?.to_str()?;
+    overrides.iter()
+        .find( |&(key, _)| key.trim_left_matches( '.' ) == extension )
+        .map( |(_, mime)| mime.as_str() )
+}
+
+// The `Content-Type` is always left alone, since that one
+// already comes from the `[server] mime` overrides.
+fn with_extra_headers( mut response: rouille::Response, headers: &BTreeMap< String, String > ) -> rouille::Response {
+    for (name, value) in headers {
+        if !name.eq_ignore_ascii_case( "Content-Type" ) {
+            response = response.with_unique_header( name.clone(), value.clone() );
+        }
+    }
+
+    response
+}
+
+fn basic_auth_credentials< 'a >( matches: &clap::ArgMatches< 'a > ) -> Option< (String, String) > {
+    let value = matches.value_of( "basic-auth" )?;
+    let separator = value.find( ':' ).unwrap();
+    Some( (value[ ..separator ].to_owned(), value[ separator + 1.. ].to_owned()) )
+}
+
 fn address_or_default< 'a >( matches: &clap::ArgMatches< 'a > ) -> net::SocketAddr {
     let host = matches.value_of( "host" ).unwrap_or( "localhost" );
     let port = matches.value_of( "port" ).unwrap_or( "8000" );
     format!( "{}:{}", host, port ).to_socket_addrs().unwrap().next().unwrap()
 }
 
-pub fn command_start< 'a >( matches: &clap::ArgMatches< 'a >, project: &CargoProject ) -> Result< (), Error > {
-    let build_matcher = BuildArgsMatcher {
-        matches: matches,
-        project: project
+// A server bound to e.g. `0.0.0.0` is reachable through `localhost`,
+// while the unspecified address itself isn't something to browse to.
+fn browsable_url( address: net::SocketAddr ) -> String {
+    if address.ip().is_unspecified() {
+        format!( "http://localhost:{}", address.port() )
+    } else {
+        format!( "http://{}", address )
+    }
+}
+
+fn open_in_browser( url: &str ) {
+    let mut command = if cfg!( windows ) {
+        let mut command = Command::new( "cmd" );
+        // The empty argument is the title of the window `start` would open.
+        command.args( &[ "/C", "start", "" ] );
+        command
+    } else if cfg!( target_os = "macos" ) {
+        Command::new( "open" )
+    } else if check_if_command_exists( "xdg-open", None ) {
+        Command::new( "xdg-open" )
+    } else {
+        println_err!( "warning: cannot open your browser since `xdg-open` isn't installed" );
+        return;
     };
 
+    if let Err( error ) = command.arg( url ).spawn() {
+        println_err!( "warning: cannot open your browser: {}", error );
+    }
+}
+
+pub fn command_start< 'a >( matches: &clap::ArgMatches< 'a >, project: &CargoProject ) -> Result< (), Error > {
+    let build_matcher = BuildArgsMatcher::new( matches, project )?;
+
     let package = build_matcher.package_or_default()?;
-    let config = Config::load_for_package_printing_warnings( &package ).unwrap().unwrap_or_default();
+    let config = build_matcher.config();
+    let mut worker_names = config.workers.clone().unwrap_or_default();
     let targets = build_matcher.target_or_select( package, |target| {
-        target.kind == TargetKind::Bin
+        target.kind == TargetKind::Bin && !worker_names.contains( &target.name )
     })?;
 
+    if !worker_names.is_empty() && !build_matcher.targeting_native_wasm() {
+        println_err!( "warning: `workers` from your `Web.toml` are only supported on the native wasm target; ignoring" );
+        worker_names.clear();
+    }
+
+    let mut worker_targets = Vec::new();
+    for name in &worker_names {
+        match package.targets.iter().find( |target| target.kind == TargetKind::Bin && target.name == *name ) {
+            Some( target ) => worker_targets.push( target ),
+            None => return Err( Error::ConfigurationError( format!( "there is no binary named `{}` which `workers` in your `Web.toml` refers to", name ) ) )
+        }
+    }
+
     if targets.is_empty() {
         return Err(
             Error::ConfigurationError( format!( "cannot start a webserver for a crate which is a library!" ) )
@@ -221,21 +507,48 @@ pub fn command_start< 'a >( matches: &clap::ArgMatches< 'a >, project: &CargoPro
     }
 
     let auto_reload = matches.is_present( "auto-reload" );
-    let target = &targets[ 0 ];
-    let builder = build_matcher.prepare_builder( &config, package, target, Profile::Main );
-    let result = builder.run()?;
-    let outputs = result_to_outputs( result );
+    let source_root = if matches.is_present( "serve-sources" ) {
+        Some( package.crate_root.canonicalize().unwrap() )
+    } else {
+        None
+    };
+    let target = targets[ 0 ];
+    let mut builders = vec![ build_matcher.prepare_builder( config, package, target, Profile::Main )? ];
+    for worker_target in &worker_targets {
+        builders.push( build_matcher.prepare_builder( config, package, worker_target, Profile::Main )? );
+    }
+
+    let steps = WatchSteps {
+        crate_root: package.crate_root.clone(),
+        pre_build: config.watch.as_ref().and_then( |watch| watch.pre_build.clone() ),
+        post_build: config.watch.as_ref().and_then( |watch| watch.post_build.clone() )
+    };
+
+    let outputs = steps.run( &builders, &mut Vec::new() )?;
+    let worker_scripts: Vec< (String, String) > = worker_names.iter().zip( outputs.iter().skip( 1 ) ).filter_map( |(name, outputs)| {
+        let script = outputs.iter().find( |output| output.is_js() )?;
+        Some( (name.clone(), script.path.file_name()?.to_string_lossy().into_owned()) )
+    }).collect();
+
+    let outputs = outputs.into_iter().flat_map( |outputs| outputs ).collect();
     let timestamp = SystemTime::now().duration_since( UNIX_EPOCH ).unwrap();
     let counter_seed = hash( timestamp.as_secs() ) ^ hash( timestamp.subsec_nanos() );
     let last_build = LastBuild {
         counter_seed,
         counter: 0,
-        outputs
+        outputs,
+        error: None
     };
     let last_build = Arc::new( Mutex::new( last_build ) );
 
+    let watch = !matches.is_present( "no-watch" );
     #[allow(unused_variables)]
-    let watcher = monitor_for_changes_and_rebuild( &package, &target, builder, last_build.clone() );
+    let watcher = if watch {
+        let watched_targets: Vec< _ > = iter::once( target ).chain( worker_targets.iter().cloned() ).collect();
+        Some( monitor_for_changes_and_rebuild( &package, &watched_targets, steps, builders, last_build.clone() ) )
+    } else {
+        None
+    };
 
     let crate_static_path = package.crate_root.join( "static" );
     let target_static_path = match target.kind {
@@ -244,27 +557,68 @@ pub fn command_start< 'a >( matches: &clap::ArgMatches< 'a >, project: &CargoPro
         _ => None
     };
 
+    let index_template = index_template_path( config, package )?;
+    let spa = matches.is_present( "spa" );
+    let not_found_page = config.server.as_ref()
+        .and_then( |server| server.not_found_page.as_ref() )
+        .map( |path| package.crate_root.join( path ) );
+    let mime_overrides = config.server.as_ref().and_then( |server| server.mime.clone() ).unwrap_or_default();
+    let serving_sources = source_root.is_some();
+    let served_worker_scripts = worker_scripts.clone();
+    let content_security_policy = config.content_security_policy.as_ref()
+        .and_then( |policy| policy.directives() )
+        .map( |directives| directives.to_owned() );
+    let basic_auth = basic_auth_credentials( matches );
+    let requires_auth = basic_auth.is_some();
     let address = address_or_default( matches );
-    let server = rouille::Server::new( &address, move |request| {
+    let extra_headers = config.dev_server.as_ref().and_then( |dev_server| dev_server.headers.clone() ).unwrap_or_default();
+    if extra_headers.keys().any( |name| name.eq_ignore_ascii_case( "Content-Type" ) ) {
+        println_err!( "warning: `Content-Type` in the `[dev-server] headers` of your `Web.toml` is ignored; use `[server] mime` instead" );
+    }
+
+    let handler = move |request: &rouille::Request| {
+        // This also covers the auto-reload polling since it goes through here too.
+        if let Some( (ref login, ref password) ) = basic_auth {
+            match rouille::input::basic_http_auth( request ) {
+                Some( ref credentials ) if credentials.login == *login && credentials.password == *password => {},
+                _ => return rouille::Response::basic_http_auth_login_required( "cargo-web" )
+            }
+        }
+
         let mut response;
+        let with_mime_override = |response: rouille::Response| {
+            match mime_override( &mime_overrides, &request.url() ) {
+                Some( mime ) => response.with_unique_header( "Content-Type", mime.to_owned() ),
+                None => response
+            }
+        };
 
         if let Some( ref target_static_path ) = target_static_path {
             response = rouille::match_assets( &request, target_static_path );
             if response.is_success() {
-                return response.with_no_cache();
+                return with_mime_override( response ).with_no_cache();
             }
         }
 
         response = rouille::match_assets( &request, &crate_static_path );
         if response.is_success() {
-            return response.with_no_cache();
+            return with_mime_override( response ).with_no_cache();
         }
 
         let last_build = last_build.lock().unwrap();
         let url = request.url();
-        if url == "/" || url == "index.html" {
-            let mut data = target_static_path.as_ref().and_then( |path| {
-                read( path.join( "index.html" ) ).ok()
+        let render_index = || {
+            let rendered_template = index_template.as_ref().and_then( |path| read( path ).ok() ).map( |template| {
+                let wasm_filename = last_build.outputs.iter()
+                    .find( |output| output.has_extension( "wasm" ) )
+                    .and_then( |output| output.path.file_name() )
+                    .map( |name| name.to_string_lossy().into_owned() );
+
+                wasm::render_index_template( &template, "js/app.js", wasm_filename.as_ref().map( |name| name.as_str() ) )
+            });
+
+            let mut data = rendered_template.or_else( || {
+                target_static_path.as_ref().and_then( |path| read( path.join( "index.html" ) ).ok() )
             }).or_else( || {
                 read( crate_static_path.join( "index.html" ) ).ok()
             }).unwrap_or_else( || DEFAULT_INDEX_HTML.to_owned() );
@@ -274,7 +628,27 @@ pub fn command_start< 'a >( matches: &clap::ArgMatches< 'a >, project: &CargoPro
                 data = data.replace( "<head>", &format!( "<head><script>{}</script>", injected_code ) );
             }
 
-            return rouille::Response::html( data ).with_no_cache();
+            if !served_worker_scripts.is_empty() {
+                let injected_code = spawn_workers_code( &served_worker_scripts );
+                data = data.replace( "</head>", &format!( "<script>{}</script></head>", injected_code ) );
+            }
+
+            // Every script gets the nonce, including the inline ones
+            // from the user's own `index.html` or template.
+            let nonce = content_security_policy.as_ref().map( |_| random_nonce() );
+            if let Some( ref nonce ) = nonce {
+                data = add_script_nonce( &data, nonce );
+            }
+
+            let response = rouille::Response::html( data ).with_no_cache();
+            match (content_security_policy.as_ref(), nonce) {
+                (Some( policy ), Some( nonce )) => response.with_unique_header( "Content-Security-Policy", wasm::add_script_source( policy, &format!( "'nonce-{}'", nonce ) ) ),
+                _ => response
+            }
+        };
+
+        if url == "/" || url == "index.html" {
+            return render_index();
         }
 
         if url == "/js/app.js" {
@@ -287,6 +661,14 @@ pub fn command_start< 'a >( matches: &clap::ArgMatches< 'a >, project: &CargoPro
              return rouille::Response::from_data( "application/text", data ).with_no_cache();
          }
 
+        if url == "/__cargo-web__/build_status" {
+            let data = json!({
+                "hash": last_build.get_build_hash(),
+                "error": last_build.error
+            });
+            return rouille::Response::from_data( "application/json", data.to_string() ).with_no_cache();
+        }
+
         let requested_file = if url.starts_with( '/' ) {
             &url[ 1.. ]
         } else {
@@ -304,10 +686,31 @@ pub fn command_start< 'a >( matches: &clap::ArgMatches< 'a >, project: &CargoPro
                 _ => "application/octet-stream"
             };
 
-            return rouille::Response::from_data( mime, output.data.clone() ).with_no_cache();
+            let response = rouille::Response::from_data( mime, output.data.clone() );
+            return with_mime_override( response ).with_no_cache();
+        }
+
+        if let Some( ref source_root ) = source_root {
+            if let Some( data ) = find_source_file( source_root, &url ).and_then( |path| read_bytes( path ).ok() ) {
+                return rouille::Response::from_data( "text/plain; charset=utf-8", data ).with_no_cache();
+            }
+        }
+
+        // Single page applications do their own routing, so
+        // every unknown path has to lead to the application.
+        if spa {
+            return render_index();
+        }
+
+        if let Some( data ) = not_found_page.as_ref().and_then( |path| read_bytes( path ).ok() ) {
+            return rouille::Response::from_data( "text/html; charset=utf-8", data ).with_status_code( 404 ).with_no_cache();
         }
 
         rouille::Response::empty_404().with_no_cache()
+    };
+
+    let server = rouille::Server::new( &address, move |request| {
+        with_extra_headers( handler( request ), &extra_headers )
     }).unwrap();
 
     println_err!( "" );
@@ -319,12 +722,84 @@ pub fn command_start< 'a >( matches: &clap::ArgMatches< 'a >, project: &CargoPro
         _ => unreachable!()
     };
     println_err!( "" );
-    println_err!( "Your application is being served at '/js/app.js'. It will be automatically" );
-    println_err!( "rebuilt if you make any changes in your code." );
+    if watch {
+        println_err!( "Your application is being served at '/js/app.js'. It will be automatically" );
+        println_err!( "rebuilt if you make any changes in your code." );
+    } else {
+        println_err!( "Your application is being served at '/js/app.js'." );
+    }
+    for &(ref name, ref script) in &worker_scripts {
+        println_err!( "The `{}` worker is being served at '/{}' and spawned into `__cargo_web.workers`.", name, script );
+    }
+    if serving_sources {
+        println_err!( "" );
+        println_err!( "The source files of your crate are also being served for in-browser debugging." );
+    }
+    if requires_auth {
+        println_err!( "" );
+        println_err!( "Every request needs to authenticate with the credentials passed to `--basic-auth`." );
+    }
     println_err!( "" );
     println_err!( "You can access the web server at `http://{}`.", &address );
 
+    // The initial build has already succeeded at this point.
+    if matches.is_present( "open" ) {
+        open_in_browser( &browsable_url( server.server_addr() ) );
+    }
+
     server.run();
 
     Ok(())
 }
+
+#[test]
+fn test_with_extra_headers() {
+    let mut headers = BTreeMap::new();
+    headers.insert( "Cross-Origin-Opener-Policy".to_owned(), "same-origin".to_owned() );
+    headers.insert( "Cross-Origin-Embedder-Policy".to_owned(), "require-corp".to_owned() );
+    headers.insert( "content-type".to_owned(), "text/plain".to_owned() );
+
+    let response = with_extra_headers( rouille::Response::from_data( "application/wasm", Vec::new() ), &headers );
+    let header = |name: &str| response.headers.iter()
+        .filter( |&&(ref key, _)| key.eq_ignore_ascii_case( name ) )
+        .map( |&(_, ref value)| value.as_ref() )
+        .collect::< Vec< _ > >();
+
+    assert_eq!( header( "Cross-Origin-Opener-Policy" ), vec![ "same-origin" ] );
+    assert_eq!( header( "Cross-Origin-Embedder-Policy" ), vec![ "require-corp" ] );
+    assert_eq!( header( "Content-Type" ), vec![ "application/wasm" ] );
+}
+
+#[test]
+fn test_mime_override() {
+    let mut overrides = BTreeMap::new();
+    overrides.insert( "data".to_owned(), "application/octet-stream".to_owned() );
+    overrides.insert( ".wasm".to_owned(), "application/x-custom".to_owned() );
+
+    assert_eq!( mime_override( &overrides, "/assets/app.data" ), Some( "application/octet-stream" ) );
+    assert_eq!( mime_override( &overrides, "/app.wasm" ), Some( "application/x-custom" ) );
+    assert_eq!( mime_override( &overrides, "/app.js" ), None );
+    assert_eq!( mime_override( &overrides, "/" ), None );
+}
+
+#[test]
+fn test_browsable_url() {
+    assert_eq!( browsable_url( "0.0.0.0:8000".parse().unwrap() ), "http://localhost:8000" );
+    assert_eq!( browsable_url( "127.0.0.1:3000".parse().unwrap() ), "http://127.0.0.1:3000" );
+}
+
+#[test]
+fn test_add_script_nonce() {
+    let html = "<head><script>a()</script><script src=\"js/app.js\"></script><script nonce=\"x\"></script><scripts></scripts></head>";
+    assert_eq!(
+        add_script_nonce( html, "abc" ),
+        "<head><script nonce=\"abc\">a()</script><script nonce=\"abc\" src=\"js/app.js\"></script><script nonce=\"x\"></script><scripts></scripts></head>"
+    );
+}
+
+#[test]
+fn test_random_nonce() {
+    let nonce = random_nonce();
+    assert_eq!( nonce.len(), 32 );
+    assert_ne!( nonce, random_nonce() );
+}
@@ -1,9 +1,15 @@
-use std::process::{Command, exit};
+use std::process::{Child, Command, ExitStatus, Stdio, exit};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::io::{self, Read};
+use std::thread;
 use std::iter;
 use std::env;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 
 use clap;
+use sha1::Sha1;
+use serde_json;
 
 use cargo_shim::{
     Profile,
@@ -13,110 +19,662 @@ use cargo_shim::{
 };
 
 use build::BuildArgsMatcher;
-use config::Config;
 use error::Error;
 use utils::{
-    CommandExt,
+    read,
     check_if_command_exists
 };
-use test_chromium::test_in_chromium;
+use test_chromium::{DEFAULT_CHROMIUM_LAUNCH_RETRIES, test_in_chromium};
+use test_firefox::test_in_firefox;
 
-fn test_in_nodejs(
+fn find_nodejs() -> Result< &'static str, Error > {
+    if cfg!( windows ) && check_if_command_exists( "node.exe", None ) {
+        Ok( "node.exe" )
+    } else if check_if_command_exists( "nodejs", None ) {
+        Ok( "nodejs" )
+    } else if check_if_command_exists( "node", None ) {
+        Ok( "node" )
+    } else {
+        Err( Error::EnvironmentError( "node.js not found; please install it!".into() ) )
+    }
+}
+
+/// The oldest version of Node.js which can run our `.wasm` files.
+pub const MINIMUM_NODEJS_VERSION: (u32, u32, u32) = (8, 0, 0);
+
+// The ranges of versions (from inclusive, to exclusive) which need an extra
+// flag to run our `.wasm` files, e.g. if they depend on a feature which was
+// still experimental there. None of the supported versions need one yet.
+const NODEJS_WASM_FLAGS: &'static [((u32, u32, u32), (u32, u32, u32), &'static str)] = &[];
+
+fn parse_nodejs_version( output: &str ) -> Option< (u32, u32, u32) > {
+    let mut parts = output.trim().trim_left_matches( 'v' ).split( '.' ).map( |part| part.parse().ok() );
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some( Some( major ) ), Some( Some( minor ) ), Some( Some( patch ) )) => Some( (major, minor, patch) ),
+        _ => None
+    }
+}
+
+fn nodejs_version( nodejs_name: &str ) -> Option< (u32, u32, u32) > {
+    let output = Command::new( nodejs_name ).arg( "--version" ).output().ok()?;
+    parse_nodejs_version( &String::from_utf8_lossy( &output.stdout ) )
+}
+
+/// Prepares a command running Node.js; when targeting WebAssembly its version
+/// is checked and any flags it needs to run the `.wasm` files are added.
+pub fn nodejs_command( build_matcher: &BuildArgsMatcher ) -> Result< Command, Error > {
+    let nodejs_name = find_nodejs()?;
+    let mut command = Command::new( nodejs_name );
+    if !build_matcher.targeting_wasm() {
+        return Ok( command );
+    }
+
+    let version = match nodejs_version( nodejs_name ) {
+        Some( version ) => version,
+        None => {
+            println_err!( "warning: cannot figure out the version of `{}`; assuming it can run WebAssembly", nodejs_name );
+            return Ok( command );
+        }
+    };
+
+    if version < MINIMUM_NODEJS_VERSION {
+        let (major, minor, patch) = MINIMUM_NODEJS_VERSION;
+        return Err( Error::EnvironmentError( format!(
+            "node.js {}.{}.{} is too old to run WebAssembly; please upgrade it to at least {}.{}.{}",
+            version.0, version.1, version.2,
+            major, minor, patch
+        )));
+    }
+
+    for &(from, to, flag) in NODEJS_WASM_FLAGS {
+        if version >= from && version < to {
+            command.arg( flag );
+        }
+    }
+
+    Ok( command )
+}
+
+fn parse_test_env( value: &str ) -> Result< (String, String), Error > {
+    match value.find( '=' ) {
+        Some( index ) if index > 0 => Ok( (value[ ..index ].to_owned(), value[ index + 1.. ].to_owned()) ),
+        _ => Err( Error::ConfigurationError( format!( "invalid `--test-env` value `{}`; expected `KEY=VALUE`", value ) ) )
+    }
+}
+
+/// The environment variables passed with `--test-env`.
+pub fn test_env( build_matcher: &BuildArgsMatcher ) -> Result< Vec< (String, String) >, Error > {
+    build_matcher.matches.values_of( "test-env" ).into_iter()
+        .flat_map( |values| values )
+        .map( parse_test_env )
+        .collect()
+}
+
+/// Finds the build's artifact with the given extension.
+pub fn find_artifact< 'a >( build: &'a CargoResult, extension: &str ) -> Result< &'a PathBuf, Error > {
+    let artifact = build.artifacts().iter().find( |artifact| artifact.extension().map( |ext| ext == extension ).unwrap_or( false ) );
+    artifact.ok_or_else( || {
+        let produced: Vec< String > = build.artifacts().iter()
+            .filter_map( |artifact| artifact.file_name() )
+            .map( |name| format!( "`{}`", name.to_string_lossy() ) )
+            .collect();
+
+        Error::NoArtifact( format!(
+            "expected the build to produce a `.{}` file, but it produced {}",
+            extension,
+            if produced.is_empty() { "nothing".to_owned() } else { produced.join( ", " ) }
+        ))
+    })
+}
+
+// Returns the `.js` artifact to run along with the directory
+// it needs to be run from.
+//
+// That's wherever the `.wasm` file ended up, since the Emscripten runtime
+// loads it from the current directory; depending on the kind of the target
+// it's either next to the `.js` file or in one of cargo's subdirectories
+// (e.g. `deps` for tests), so it's taken from the artifacts instead of guessed.
+fn nodejs_entry_point( build_matcher: &BuildArgsMatcher, build: &CargoResult ) -> Result< (PathBuf, PathBuf), Error > {
+    let artifact = find_artifact( build, "js" )?;
+    let working_directory = if build_matcher.targeting_emscripten_asmjs() {
+        artifact.parent().unwrap().to_owned()
+    } else {
+        find_artifact( build, "wasm" )?.parent().unwrap().to_owned()
+    };
+
+    Ok( (artifact.clone(), working_directory) )
+}
+
+// Changes the current directory until it's dropped,
+// so it gets restored even if we bail out early.
+struct CurrentDirGuard {
+    previous: PathBuf
+}
+
+impl CurrentDirGuard {
+    fn change_to( path: &Path ) -> Result< Self, Error > {
+        let previous = env::current_dir()
+            .map_err( |err| Error::RuntimeError( "cannot get the current directory".into(), err.into() ) )?;
+        env::set_current_dir( path )
+            .map_err( |err| Error::RuntimeError( format!( "cannot change the current directory to {:?}", path ), err.into() ) )?;
+
+        Ok( CurrentDirGuard { previous } )
+    }
+}
+
+impl Drop for CurrentDirGuard {
+    fn drop( &mut self ) {
+        if let Err( err ) = env::set_current_dir( &self.previous ) {
+            println_err!( "warning: cannot change the current directory back to {:?}: {}", self.previous, err );
+        }
+    }
+}
+
+// Waits for the child to exit; if it's still running after
+// `timeout` it gets killed and `None` is returned instead.
+fn wait_with_timeout( child: &mut Child, timeout: Option< Duration > ) -> io::Result< Option< ExitStatus > > {
+    let timeout = match timeout {
+        Some( timeout ) => timeout,
+        None => return child.wait().map( Some )
+    };
+
+    let start = Instant::now();
+    loop {
+        if let Some( status ) = child.try_wait()? {
+            return Ok( Some( status ) );
+        }
+
+        if start.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            println_err!( "error: test binary timed out after {} seconds", timeout.as_secs() );
+            return Ok( None );
+        }
+
+        thread::sleep( Duration::from_millis( 50 ) );
+    }
+}
+
+// Node.js only treats what's before the script as its own options,
+// so the filters and flags like `--nocapture` have to come after it
+// to end up in `process.argv` where the test harness looks for them.
+fn nodejs_test_args< 'a >( artifact: &'a Path, arg_passthrough: &[&'a OsStr] ) -> Vec< &'a OsStr > {
+    iter::once( artifact.as_os_str() )
+        .chain( arg_passthrough.iter().cloned() )
+        .collect()
+}
+
+/// Remembers that a test binary has failed. Only the first failure's exit
+/// code is kept; `None` (e.g. killed by a signal or timed out) becomes `101`.
+pub fn record_failure( failure_code: &mut Option< i32 >, code: Option< i32 > ) {
+    if failure_code.is_none() {
+        *failure_code = Some( code.unwrap_or( 101 ) );
+    }
+}
+
+pub fn test_in_nodejs(
     build_matcher: &BuildArgsMatcher,
     build: CargoResult,
     arg_passthrough: &Vec< &OsStr >,
-    any_failure: &mut bool
+    timeout: Option< Duration >,
+    failure_code: &mut Option< i32 >
 ) -> Result< (), Error > {
 
-    let nodejs_name =
-        if cfg!( windows ) && check_if_command_exists( "node.exe", None ) {
-            "node.exe"
-        } else if check_if_command_exists( "nodejs", None ) {
-            "nodejs"
-        } else if check_if_command_exists( "node", None ) {
-            "node"
+    let mut command = nodejs_command( build_matcher )?;
+    let (artifact, working_directory) = nodejs_entry_point( build_matcher, &build )?;
+
+    let test_args = nodejs_test_args( &artifact, arg_passthrough );
+
+    let status = {
+        let _cwd = CurrentDirGuard::change_to( &working_directory )?;
+        command.args( test_args ).envs( test_env( build_matcher )? ).spawn()
+            .and_then( |mut child| wait_with_timeout( &mut child, timeout ) )
+            .map_err( |err| Error::RuntimeError( "cannot run node.js".into(), err.into() ) )?
+    };
+    match status {
+        Some( status ) if status.success() => {},
+        Some( status ) => record_failure( failure_code, status.code() ),
+        None => record_failure( failure_code, None )
+    }
+
+    Ok(())
+}
+
+const TEST_REPORT_PREFIX: &'static str = "cargo-web-test-report: ";
+
+// Printed by the runtime when `CARGO_WEB_TEST_REPORT` is set, since
+// on the native wasm target the test harness can't print anything.
+#[derive(Deserialize, Debug)]
+struct TestReport {
+    status: String,
+    message: Option< String >
+}
+
+fn parse_test_report( stdout: &str ) -> Option< TestReport > {
+    stdout.lines()
+        .filter( |line| line.starts_with( TEST_REPORT_PREFIX ) )
+        .last()
+        .and_then( |line| serde_json::from_str( &line[ TEST_REPORT_PREFIX.len().. ] ).ok() )
+}
+
+// Returns whether the tests have passed.
+fn test_native_wasm_in_nodejs(
+    build_matcher: &BuildArgsMatcher,
+    build: &CargoResult,
+    arg_passthrough: &[&OsStr],
+    timeout: Option< Duration >
+) -> Result< bool, Error > {
+    let (artifact, working_directory) = nodejs_entry_point( build_matcher, build )?;
+    let mut child = nodejs_command( build_matcher )?
+        .arg( &artifact )
+        .args( arg_passthrough )
+        .envs( test_env( build_matcher )? )
+        .env( "CARGO_WEB_TEST_REPORT", "1" )
+        .current_dir( working_directory )
+        .stdout( Stdio::piped() )
+        .stderr( Stdio::inherit() )
+        .spawn()
+        .map_err( |err| Error::RuntimeError( "cannot launch node.js".into(), err.into() ) )?;
+
+    let mut child_stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn( move || {
+        let mut stdout = Vec::new();
+        let _ = child_stdout.read_to_end( &mut stdout );
+        stdout
+    });
+
+    let status = wait_with_timeout( &mut child, timeout )
+        .map_err( |err| Error::RuntimeError( "cannot run node.js".into(), err.into() ) )?;
+
+    let stdout = reader.join().unwrap();
+    let stdout = String::from_utf8_lossy( &stdout );
+    for line in stdout.lines().filter( |line| !line.starts_with( TEST_REPORT_PREFIX ) ) {
+        println!( "{}", line );
+    }
+
+    let name = artifact.file_name().unwrap();
+    let status = match status {
+        Some( status ) => status,
+        None => return Ok( false )
+    };
+
+    match parse_test_report( &stdout ) {
+        Some( ref report ) if report.status == "ok" && status.success() => Ok( true ),
+        Some( TestReport { status, message } ) => {
+            println_err!( "error: the tests in {:?} have failed: {}", name, message.unwrap_or( status ) );
+            Ok( false )
+        },
+        None => {
+            println_err!( "error: the tests in {:?} exited with {} without reporting their results", name, status );
+            Ok( false )
+        }
+    }
+}
+
+// The file contains one test name pattern per line; empty lines
+// and lines starting with `#` are ignored. Patterns prefixed with
+// a `!` are translated into `--skip`, and the rest are passed
+// to the test harness as ordinary filters.
+fn parse_test_filters( contents: &str ) -> Vec< OsString > {
+    let mut args = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with( '#' ) {
+            continue;
+        }
+
+        if line.starts_with( '!' ) {
+            let pattern = line[ 1.. ].trim();
+            if !pattern.is_empty() {
+                args.push( "--skip".into() );
+                args.push( pattern.into() );
+            }
         } else {
-            return Err( Error::EnvironmentError( "node.js not found; please install it!".into() ) );
-        };
+            args.push( line.into() );
+        }
+    }
 
-    let artifact = build.artifacts().iter()
-        .find( |artifact| artifact.extension().map( |ext| ext == "js" ).unwrap_or( false ) )
-        .expect( "internal error: no .js file found" );
+    args
+}
 
-    let test_args = iter::once( artifact.as_os_str() )
-        .chain( arg_passthrough.iter().cloned() );
+fn load_test_filters( path: &OsStr ) -> Result< Vec< OsString >, Error > {
+    let contents = read( path )
+        .map_err( |err| Error::RuntimeError( format!( "cannot read the test filter file {:?}", path ), err.into() ) )?;
 
-    let previous_cwd = env::current_dir().unwrap();
-    if build_matcher.targeting_emscripten_wasm() {
-        // On the Emscripten target the `.wasm` file is in a different directory.
-        let wasm_artifact = build.artifacts().iter()
-            .find( |artifact| artifact.extension().map( |ext| ext == "wasm" ).unwrap_or( false ) )
-            .expect( "internal error: no .wasm file found" );
+    Ok( parse_test_filters( &contents ) )
+}
 
-        env::set_current_dir( wasm_artifact.parent().unwrap() ).unwrap();
-    } else {
-        env::set_current_dir( artifact.parent().unwrap() ).unwrap();
+fn list_tests_in_nodejs( build_matcher: &BuildArgsMatcher, build: &CargoResult ) -> Result< Vec< String >, Error > {
+    let (artifact, working_directory) = nodejs_entry_point( build_matcher, build )?;
+    let output = nodejs_command( build_matcher )?
+        .arg( &artifact )
+        .arg( "--list" )
+        .current_dir( working_directory )
+        .output()
+        .map_err( |err| Error::RuntimeError( "cannot launch node.js".into(), err.into() ) )?;
+
+    if !output.status.success() {
+        return Err( Error::EnvironmentError( format!( "failed to list the tests in {:?}", artifact ) ) );
     }
 
-    let status = Command::new( nodejs_name ).args( test_args ).run();
-    *any_failure = *any_failure || !status.is_ok();
+    let stdout = String::from_utf8_lossy( &output.stdout );
+    let tests = stdout.lines()
+        .filter( |line| line.ends_with( ": test" ) )
+        .map( |line| line[ ..line.len() - ": test".len() ].to_owned() )
+        .collect();
 
-    env::set_current_dir( previous_cwd ).unwrap();
+    Ok( tests )
+}
 
-    Ok(())
+// Only the function imports can be stubbed out, which is fine since
+// that's all the native wasm target's modules are importing.
+const INSTANTIATION_HARNESS: &'static str = r#"
+    const bytes = require( "fs" ).readFileSync( process.argv[ process.argv.length - 1 ] );
+    const start = process.hrtime();
+    const mod = new WebAssembly.Module( bytes );
+    const imports = {};
+    for( const entry of WebAssembly.Module.imports( mod ) ) {
+        if( entry.kind !== "function" ) {
+            throw new Error( "cannot stub out a non-function import: " + entry.module + "." + entry.name );
+        }
+        imports[ entry.module ] = imports[ entry.module ] || {};
+        imports[ entry.module ][ entry.name ] = function() {};
+    }
+    new WebAssembly.Instance( mod, imports );
+    const elapsed = process.hrtime( start );
+    console.log( elapsed[ 0 ] * 1000 + elapsed[ 1 ] / 1000000 );
+"#;
+
+fn measure_instantiation_time( build_matcher: &BuildArgsMatcher, build: &CargoResult ) -> Result< f64, Error > {
+    let wasm_artifact = find_artifact( build, "wasm" )?;
+    let output = nodejs_command( build_matcher )?
+        .arg( "-e" )
+        .arg( INSTANTIATION_HARNESS )
+        .arg( wasm_artifact )
+        .output()
+        .map_err( |err| Error::RuntimeError( "cannot launch node.js".into(), err.into() ) )?;
+
+    if !output.status.success() {
+        return Err( Error::EnvironmentError( format!(
+            "failed to measure how long {:?} takes to instantiate: {}",
+            wasm_artifact.file_name().unwrap(),
+            String::from_utf8_lossy( &output.stderr ).trim()
+        )));
+    }
+
+    let stdout = String::from_utf8_lossy( &output.stdout );
+    stdout.trim().parse().map_err( |err| Error::RuntimeError( "cannot parse the instantiation time".into(), Box::new( err ) ) )
+}
+
+fn parse_test_shard( value: &str ) -> Result< (u32, u32), Error > {
+    let error = || Error::ConfigurationError( format!( "invalid `--test-shard` value `{}`; expected `I/N` where 1 <= I <= N", value ) );
+    let mut parts = value.splitn( 2, '/' );
+    let index: u32 = parts.next().and_then( |part| part.trim().parse().ok() ).ok_or_else( error )?;
+    let count: u32 = parts.next().and_then( |part| part.trim().parse().ok() ).ok_or_else( error )?;
+    if index == 0 || index > count {
+        return Err( error() );
+    }
+
+    Ok( (index, count) )
+}
+
+// The shard is picked by hashing the name of the test, so that the
+// partitioning is stable across machines and doesn't depend on
+// the order in which the tests were discovered.
+fn test_shard_of( name: &str, count: u32 ) -> u32 {
+    let mut hasher = Sha1::new();
+    hasher.update( name.as_bytes() );
+    let digest = hasher.digest().bytes();
+    let hash = (digest[ 0 ] as u32) << 24 | (digest[ 1 ] as u32) << 16 | (digest[ 2 ] as u32) << 8 | digest[ 3 ] as u32;
+    hash % count + 1
+}
+
+// The tests of the shard are passed as exact filters; the ones from
+// the other shards would be far more numerous if they were skipped instead.
+fn test_shard_args( (index, count): (u32, u32), tests: &[String] ) -> Vec< OsString > {
+    let mut args = vec![ OsString::from( "--exact" ) ];
+    for test in tests {
+        if test_shard_of( test, count ) == index {
+            args.push( test.into() );
+        }
+    }
+
+    args
+}
+
+// The options of the test harness which take a separate value.
+const TEST_HARNESS_OPTIONS_WITH_VALUES: &'static [&'static str] = &[ "--test-threads", "--logfile", "--format", "--color", "-Z" ];
+
+// Whether the arguments meant for the test harness filter the tests,
+// either through a positional filter or through a `--skip`.
+fn has_test_filters( args: &[&OsStr] ) -> bool {
+    let mut args = args.iter();
+    while let Some( arg ) = args.next() {
+        let arg = arg.to_string_lossy();
+        if arg == "--skip" || !arg.starts_with( '-' ) {
+            return true;
+        }
+
+        if TEST_HARNESS_OPTIONS_WITH_VALUES.contains( &&*arg ) {
+            args.next();
+        }
+    }
+
+    false
 }
 
 pub fn command_test< 'a >( matches: &clap::ArgMatches< 'a >, project: &CargoProject ) -> Result< (), Error > {
-    let build_matcher = BuildArgsMatcher {
-        matches: matches,
-        project: project
-    };
+    let build_matcher = BuildArgsMatcher::new( matches, project )?;
 
     let use_nodejs = matches.is_present( "nodejs" );
+    let use_firefox = matches.is_present( "firefox" );
     let no_run = matches.is_present( "no-run" );
     if build_matcher.targeting_native_wasm() && !use_nodejs {
         return Err( Error::ConfigurationError( "running tests for the native wasm target is currently only supported with `--nodejs`".into() ) );
     }
 
-    let arg_passthrough = matches.values_of_os( "passthrough" )
-        .map_or( vec![], |args| args.collect() );
+    let test_shard = match matches.value_of( "test-shard" ) {
+        Some( value ) => Some( parse_test_shard( value )? ),
+        None => None
+    };
+
+    if test_shard.is_some() && (!use_nodejs || build_matcher.targeting_native_wasm()) {
+        return Err( Error::ConfigurationError( "`--test-shard` is currently only supported with `--nodejs` on the Emscripten targets".into() ) );
+    }
+
+    // Only to report a malformed one before anything gets built.
+    test_env( &build_matcher )?;
+
+    let filter_args = match matches.value_of_os( "test-filter-file" ) {
+        Some( path ) => load_test_filters( path )?,
+        None => Vec::new()
+    };
+
+    let arg_passthrough: Vec< &OsStr > = matches.values_of_os( "passthrough" )
+        .map_or( vec![], |args| args.collect() )
+        .into_iter()
+        .chain( filter_args.iter().map( |arg| arg.as_os_str() ) )
+        .collect();
+
+    // Together with `--exact` the filters would no longer match by substring.
+    if test_shard.is_some() && has_test_filters( &arg_passthrough ) {
+        return Err( Error::ConfigurationError( "`--test-shard` can't be combined with test filters, including the ones from `--test-filter-file`".into() ) );
+    }
 
     let package = build_matcher.package_or_default()?;
-    let config = Config::load_for_package_printing_warnings( &package ).unwrap().unwrap_or_default();
+    let config = build_matcher.config();
     let targets = build_matcher.target_or_select( package, |target| {
         target.kind == TargetKind::Lib || target.kind == TargetKind::Bin || target.kind == TargetKind::Test
     })?;
 
+    // The examples are built as ordinary programs and only have to exit successfully.
     let mut builds = Vec::new();
+    let mut example_builds = Vec::new();
     for target in targets {
-        let builder = build_matcher.prepare_builder( &config, package, target, Profile::Test );
-        builds.push( builder.run()? );
+        if target.kind == TargetKind::Example {
+            let builder = build_matcher.prepare_builder( config, package, target, Profile::Main )?;
+            example_builds.push( builder.run()? );
+        } else {
+            let builder = build_matcher.prepare_builder( config, package, target, Profile::Test )?;
+            builds.push( builder.run()? );
+        }
     }
 
     if no_run {
         exit( 0 );
     }
 
-    let mut any_failure = false;
-    if use_nodejs {
+    let launch_retries = matches.value_of( "chromium-launch-retries" ).map( |value| value.parse().unwrap() )
+        .or_else( || config.test.as_ref().and_then( |test| test.chromium_launch_retries ) )
+        .unwrap_or( DEFAULT_CHROMIUM_LAUNCH_RETRIES );
+    let timeout = matches.value_of( "test-timeout" ).map( |value| value.parse().unwrap() )
+        .or_else( || config.test.as_ref().and_then( |test| test.timeout ) )
+        .map( Duration::from_secs );
+    let mut failure_code = None;
+    if let Some( budget ) = config.max_instantiation_ms {
+        if build_matcher.targeting_native_wasm() {
+            for build in &builds {
+                let elapsed = measure_instantiation_time( &build_matcher, build )?;
+                if elapsed > budget as f64 {
+                    println_err!( "error: instantiation took {:.1}ms which is over the budget of {}ms set by `max-instantiation-ms` in your `Web.toml`", elapsed, budget );
+                    record_failure( &mut failure_code, None );
+                } else {
+                    println_err!( "    Instantiation took {:.1}ms (budget: {}ms)", elapsed, budget );
+                }
+            }
+        } else {
+            println_err!( "warning: `max-instantiation-ms` from your `Web.toml` is only supported on the native wasm target; ignoring" );
+        }
+    }
+
+    let mut native_results = (0, 0);
+    if use_nodejs && build_matcher.targeting_native_wasm() {
+        for build in &builds {
+            if test_native_wasm_in_nodejs( &build_matcher, build, &arg_passthrough, timeout )? {
+                native_results.0 += 1;
+            } else {
+                native_results.1 += 1;
+                record_failure( &mut failure_code, None );
+            }
+        }
+    } else if use_nodejs {
+        for build in builds {
+            let shard_args = match test_shard {
+                Some( shard ) => test_shard_args( shard, &list_tests_in_nodejs( &build_matcher, &build )? ),
+                None => Vec::new()
+            };
+
+            let args: Vec< &OsStr > = arg_passthrough.iter().cloned()
+                .chain( shard_args.iter().map( |arg| arg.as_os_str() ) )
+                .collect();
+
+            test_in_nodejs( &build_matcher, build, &args, timeout, &mut failure_code )?;
+        }
+    } else if use_firefox {
         for build in builds {
-            test_in_nodejs( &build_matcher, build, &arg_passthrough, &mut any_failure )?;
+            test_in_firefox( &build_matcher, build, &arg_passthrough, timeout, &mut failure_code )?;
         }
     } else {
         for build in builds {
-            test_in_chromium( &build_matcher, build, &arg_passthrough, &mut any_failure )?;
+            test_in_chromium( &build_matcher, build, &arg_passthrough, launch_retries, timeout, &mut failure_code )?;
         }
     }
 
-    if any_failure {
-        exit( 101 );
-    } else {
-        if build_matcher.targeting_native_wasm() {
-            println_err!( "All tests passed!" );
-            // At least **I hope** that's the case; there are no prints
-            // when running those tests, so who knows what happens. *shrug*
+    // The arguments are meant for the test harness, which the examples don't have.
+    let no_args = Vec::new();
+    for build in example_builds {
+        if use_nodejs {
+            test_in_nodejs( &build_matcher, build, &no_args, timeout, &mut failure_code )?;
+        } else if use_firefox {
+            test_in_firefox( &build_matcher, build, &no_args, timeout, &mut failure_code )?;
+        } else {
+            test_in_chromium( &build_matcher, build, &no_args, launch_retries, timeout, &mut failure_code )?;
         }
     }
 
+    if build_matcher.targeting_native_wasm() {
+        // The harness only reports how the whole run went, so
+        // these are counted per test binary instead of per test.
+        let (passed, failed) = native_results;
+        println_err!( "test result: {}. {} test binaries passed; {} failed", if failed == 0 { "ok" } else { "FAILED" }, passed, failed );
+    }
+
+    if let Some( code ) = failure_code {
+        exit( code );
+    }
+
     Ok(())
 }
+
+#[test]
+fn test_parse_test_report() {
+    let report = parse_test_report( "hello\ncargo-web-test-report: {\"status\":\"failed\",\"message\":\"RuntimeError: unreachable\"}\n" ).unwrap();
+    assert_eq!( report.status, "failed" );
+    assert_eq!( report.message, Some( "RuntimeError: unreachable".to_owned() ) );
+    assert!( parse_test_report( "hello\n" ).is_none() );
+}
+
+#[test]
+fn test_parse_test_filters() {
+    let filters = parse_test_filters( "# flaky\n\nfoo::bar\n  !baz  \n!\n" );
+    assert_eq!( filters, vec![ OsString::from( "foo::bar" ), OsString::from( "--skip" ), OsString::from( "baz" ) ] );
+}
+
+#[test]
+fn test_test_shards_partition_the_tests() {
+    let tests: Vec< String > = (0..100).map( |nth| format!( "module::test_{}", nth ) ).collect();
+    let mut total = 0;
+    for index in 1..4 {
+        total += test_shard_args( (index, 3), &tests ).iter().filter( |arg| *arg != "--exact" ).count();
+    }
+
+    assert_eq!( total, tests.len() );
+    assert!( parse_test_shard( "0/3" ).is_err() );
+    assert!( parse_test_shard( "4/3" ).is_err() );
+    assert_eq!( parse_test_shard( "2/3" ).unwrap(), (2, 3) );
+}
+
+#[test]
+fn test_has_test_filters() {
+    let args = |args: &[&'static str]| -> Vec< &'static OsStr > { args.iter().map( |arg| OsStr::new( *arg ) ).collect() };
+    assert!( !has_test_filters( &args( &[ "--nocapture", "--test-threads", "1" ] ) ) );
+    assert!( has_test_filters( &args( &[ "--nocapture", "foo::bar" ] ) ) );
+    assert!( has_test_filters( &args( &[ "--skip", "foo" ] ) ) );
+}
+
+#[test]
+fn test_nodejs_test_args() {
+    let passthrough = [ OsStr::new( "my_module::" ), OsStr::new( "--nocapture" ) ];
+    let args = nodejs_test_args( Path::new( "/target/tests.js" ), &passthrough );
+    assert_eq!( args, vec![ OsStr::new( "/target/tests.js" ), OsStr::new( "my_module::" ), OsStr::new( "--nocapture" ) ] );
+}
+
+#[test]
+fn test_record_failure_keeps_the_first_code() {
+    let mut failure_code = None;
+    record_failure( &mut failure_code, Some( 3 ) );
+    record_failure( &mut failure_code, Some( 7 ) );
+    assert_eq!( failure_code, Some( 3 ) );
+
+    let mut failure_code = None;
+    record_failure( &mut failure_code, None );
+    assert_eq!( failure_code, Some( 101 ) );
+}
+
+#[test]
+fn test_parse_nodejs_version() {
+    assert_eq!( parse_nodejs_version( "v8.11.3\n" ), Some( (8, 11, 3) ) );
+    assert_eq!( parse_nodejs_version( "v10.0.0" ), Some( (10, 0, 0) ) );
+    assert_eq!( parse_nodejs_version( "v9.0" ), None );
+    assert_eq!( parse_nodejs_version( "" ), None );
+    assert!( parse_nodejs_version( "v7.10.1" ).unwrap() < MINIMUM_NODEJS_VERSION );
+}
+
+#[test]
+fn test_parse_test_env() {
+    assert_eq!( parse_test_env( "RUST_LOG=debug" ).unwrap(), ("RUST_LOG".to_owned(), "debug".to_owned()) );
+    assert_eq!( parse_test_env( "URL=http://a/?b=c" ).unwrap(), ("URL".to_owned(), "http://a/?b=c".to_owned()) );
+    assert_eq!( parse_test_env( "EMPTY=" ).unwrap(), ("EMPTY".to_owned(), "".to_owned()) );
+    assert!( parse_test_env( "=value" ).is_err() );
+    assert!( parse_test_env( "KEY" ).is_err() );
+}
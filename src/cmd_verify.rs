@@ -0,0 +1,154 @@
+use std::path::{Path, PathBuf};
+
+use clap;
+use regex::Regex;
+use serde_json::{self, Value};
+
+use cmd_deploy::DEPLOY_MANIFEST;
+use error::Error;
+use package::hash_file;
+use utils::{
+    read,
+    read_bytes
+};
+
+fn extract_script_sources( html: &str ) -> Vec< String > {
+    let regex = Regex::new( r#"<script[^>]*\bsrc\s*=\s*["']([^"']+)["']"# ).unwrap();
+    regex.captures_iter( html ).map( |captures| captures[ 1 ].to_owned() ).collect()
+}
+
+fn extract_wasm_references( js: &str ) -> Vec< String > {
+    let regex = Regex::new( r#"["']([^"'\s]+\.wasm)["']"# ).unwrap();
+    let mut references: Vec< String > = regex.captures_iter( js ).map( |captures| captures[ 1 ].to_owned() ).collect();
+    references.dedup();
+    references
+}
+
+// Returns `None` for references which don't point into
+// the deployed directory, e.g. scripts loaded from a CDN.
+fn resolve_reference( root: &Path, reference: &str ) -> Option< PathBuf > {
+    if reference.contains( "://" ) || reference.starts_with( "//" ) || reference.starts_with( "data:" ) {
+        return None;
+    }
+
+    let reference = reference.split( |ch| ch == '?' || ch == '#' ).next().unwrap();
+    Some( root.join( reference.trim_left_matches( '/' ) ) )
+}
+
+// The `(path, hash)` pairs from the manifest written by `cargo web deploy`.
+fn parse_manifest( manifest: &str ) -> Option< Vec< (String, String) > > {
+    let manifest: Value = serde_json::from_str( manifest ).ok()?;
+    manifest.get( "files" )?.as_object()?.iter()
+        .map( |(path, hash)| hash.as_str().map( |hash| (path.clone(), hash.to_owned()) ) )
+        .collect()
+}
+
+fn verify_manifest( root: &Path, problems: &mut Vec< String > ) -> Result< Option< usize >, Error > {
+    let manifest_path = root.join( DEPLOY_MANIFEST );
+    if !manifest_path.exists() {
+        return Ok( None );
+    }
+
+    let manifest = read( &manifest_path ).map_err( |error| Error::RuntimeError( format!( "cannot read {:?}", manifest_path ), error.into() ) )?;
+    let files = match parse_manifest( &manifest ) {
+        Some( files ) => files,
+        None => {
+            problems.push( format!( "`{}` isn't a valid manifest", DEPLOY_MANIFEST ) );
+            return Ok( Some( 0 ) );
+        }
+    };
+
+    for &(ref path, ref expected_hash) in &files {
+        match hash_file( &root.join( path ) ) {
+            Ok( ref hash ) if hash == expected_hash => {},
+            Ok( _ ) => problems.push( format!( "`{}` was modified after it was deployed", path ) ),
+            Err( _ ) => problems.push( format!( "`{}` is listed in `{}` but doesn't exist", path, DEPLOY_MANIFEST ) )
+        }
+    }
+
+    Ok( Some( files.len() ) )
+}
+
+pub fn command_verify< 'a >( matches: &clap::ArgMatches< 'a > ) -> Result< (), Error > {
+    let root = Path::new( matches.value_of( "directory" ).unwrap() );
+    if !root.is_dir() {
+        return Err( Error::ConfigurationError( format!( "{:?} is not a directory", root ) ) );
+    }
+
+    let mut problems = Vec::new();
+    let index_path = root.join( "index.html" );
+    let html = match read( &index_path ) {
+        Ok( html ) => html,
+        Err( error ) => return Err( Error::RuntimeError( format!( "cannot read {:?}", index_path ), error.into() ) )
+    };
+
+    let mut wasm_count = 0;
+    for source in extract_script_sources( &html ) {
+        let js_path = match resolve_reference( root, &source ) {
+            Some( path ) => path,
+            None => continue
+        };
+
+        let js = match read( &js_path ) {
+            Ok( js ) => js,
+            Err( _ ) => {
+                problems.push( format!( "`index.html` references `{}` which doesn't exist", source ) );
+                continue;
+            }
+        };
+
+        // The runtime fetches the `.wasm` file relative to the page, not to the script.
+        for reference in extract_wasm_references( &js ) {
+            let wasm_path = match resolve_reference( root, &reference ) {
+                Some( path ) => path,
+                None => continue
+            };
+
+            wasm_count += 1;
+            match read_bytes( &wasm_path ) {
+                Ok( ref data ) if data.starts_with( b"\0asm" ) => {},
+                Ok( _ ) => problems.push( format!( "`{}` (referenced by `{}`) is not a valid `.wasm` file", reference, source ) ),
+                Err( _ ) => problems.push( format!( "`{}` references `{}` which doesn't exist", source, reference ) )
+            }
+        }
+    }
+
+    // Directories which weren't made by `cargo web deploy` don't have a manifest.
+    let verified_files = verify_manifest( root, &mut problems )?;
+
+    if !problems.is_empty() {
+        for problem in &problems {
+            println_err!( "error: {}", problem );
+        }
+
+        return Err( Error::ConfigurationError( format!( "found {} problem(s) in {:?}", problems.len(), root ) ) );
+    }
+
+    println_err!( "    Verified {:?}: all of the referenced scripts and {} `.wasm` file(s) are present", root, wasm_count );
+    if let Some( count ) = verified_files {
+        println_err!( "    The hashes of all of the {} file(s) listed in `{}` match", count, DEPLOY_MANIFEST );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_extract_references() {
+    let html = r#"<script src="js/app.js"></script><script type="text/javascript" src='https://example.com/lib.js'></script>"#;
+    assert_eq!( extract_script_sources( html ), vec![ "js/app.js", "https://example.com/lib.js" ] );
+
+    let js = r#"const wasm_path = path.join( __dirname, "app.wasm" ); fetch( "app.wasm" )"#;
+    assert_eq!( extract_wasm_references( js ), vec![ "app.wasm" ] );
+
+    let root = Path::new( "/deploy" );
+    assert_eq!( resolve_reference( root, "/js/app.js?v=1" ), Some( PathBuf::from( "/deploy/js/app.js" ) ) );
+    assert_eq!( resolve_reference( root, "//cdn.example.com/lib.js" ), None );
+}
+
+#[test]
+fn test_parse_manifest() {
+    let manifest = r#"{ "files": { "app.js": "abc", "js/app.wasm": "def" } }"#;
+    assert_eq!( parse_manifest( manifest ).unwrap(), vec![ ("app.js".to_owned(), "abc".to_owned()), ("js/app.wasm".to_owned(), "def".to_owned()) ] );
+    assert!( parse_manifest( r#"{ "files": { "app.js": 1 } }"# ).is_none() );
+    assert!( parse_manifest( "" ).is_none() );
+}
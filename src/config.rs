@@ -1,22 +1,219 @@
+use std::collections::BTreeMap;
+use std::cell::RefCell;
 use std::error::Error;
 use std::io;
 use std::path::Path;
+use std::env;
 use toml;
+use serde_json::Value;
 use cargo_shim::CargoPackage;
 use utils::read;
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Config {
+    pub link_args: Option< Vec< String > >,
+    pub build_std: Option< BuildStd >,
+    pub build_info: Option< bool >,
+    pub wasm_opt_args: Option< Vec< String > >,
+    pub server: Option< ServerConfig >,
+    /// Maps the names accepted by `--allocator` to the crate features enabling them.
+    pub allocators: Option< BTreeMap< String, String > >,
+    pub test: Option< TestConfig >,
+    /// Extra features to enable only when building the target with a given name.
+    pub target_features: Option< BTreeMap< String, Vec< String > > >,
+    /// Names of the binary targets which `cargo web start` should
+    /// also build and spawn as Web Workers.
+    pub workers: Option< Vec< String > >,
+    /// Modules (`env`) or single imports (`env.foo`) which the
+    /// generated `.wasm` files are allowed to import.
+    pub allowed_imports: Option< Vec< String > >,
+    /// The only features which `--locked-features` allows
+    /// to be activated, by crate name.
+    pub expected_features: Option< BTreeMap< String, Vec< String > > >,
+    /// A command to run over every generated `.wasm` file to produce
+    /// a detached signature next to it, as `<file>.wasm.sig`.
+    pub sign_command: Option< Vec< String > >,
+    pub defaults: Option< Defaults >,
+    /// How long the `.wasm` file is allowed to take to compile and
+    /// instantiate when running the tests, in milliseconds.
+    pub max_instantiation_ms: Option< u64 >,
+    pub watch: Option< WatchConfig >,
+    /// A JavaScript file, relative to the crate's root, which provides extra
+    /// imports for the `.wasm` file on the native wasm target.
+    pub host_imports: Option< String >,
+    /// Sent as a header by `cargo web start`, and added as a `<meta>` tag to
+    /// the `index.html` written by `--generate-html` and `cargo web deploy`.
+    pub content_security_policy: Option< ContentSecurityPolicy >,
+    /// Settings which only apply when building for a given target triplet.
+    pub target: Option< BTreeMap< String, TargetConfig > >,
+    pub emscripten: Option< EmscriptenConfig >,
+    pub build: Option< BuildSettings >,
+    pub dev_server: Option< DevServerConfig >,
+    /// The values of the environment variables which were
+    /// expanded in the `Web.toml`, by their names.
+    #[serde(skip)]
+    pub expanded_variables: BTreeMap< String, String >
+}
+
+/// The default policy only allows same-origin resources, but
+/// also permits the `.wasm` file to be compiled.
+pub const DEFAULT_CONTENT_SECURITY_POLICY: &'static str = "default-src 'self'; script-src 'self' 'wasm-unsafe-eval'";
+
+/// Either `true` for the default policy, or the policy's directives.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ContentSecurityPolicy {
+    Default( bool ),
+    Custom( String )
+}
+
+impl ContentSecurityPolicy {
+    pub fn directives( &self ) -> Option< &str > {
+        match *self {
+            ContentSecurityPolicy::Default( true ) => Some( DEFAULT_CONTENT_SECURITY_POLICY ),
+            ContentSecurityPolicy::Default( false ) => None,
+            ContentSecurityPolicy::Custom( ref directives ) => Some( directives )
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BuildStd {
+    pub crates: Option< Vec< String > >,
+    pub features: Option< Vec< String > >
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ServerConfig {
+    /// Maps file extensions to the MIME types they should be served as;
+    /// takes precedence over the built-in defaults.
+    pub mime: Option< BTreeMap< String, String > >,
+    /// A page, relative to the crate's root, to serve instead of a bare 404.
+    pub not_found_page: Option< String >
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DevServerConfig {
+    /// Extra headers which `cargo web start` adds to every response,
+    /// e.g. `Cross-Origin-Opener-Policy`.
+    pub headers: Option< BTreeMap< String, String > >,
+    /// A template, relative to the crate's root, for the `index.html` which
+    /// `cargo web start` serves and `cargo web deploy` writes.
+    pub index: Option< String >
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TestConfig {
+    pub chromium_launch_retries: Option< u32 >,
+    /// How many seconds a single test binary may run for before it's killed.
+    pub timeout: Option< u64 >
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TargetConfig {
+    /// Passed to the linker after the global `link-args`.
     pub link_args: Option< Vec< String > >
 }
 
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EmscriptenConfig {
+    /// The Emscripten version to build with, e.g. `1.37.27`.
+    pub version: Option< String >,
+    /// Whether not being able to use that version is an error instead of a warning.
+    pub strict: Option< bool >
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BuildSettings {
+    /// Deprecated in favor of `target` in `[defaults]`, which it's an alias of.
+    pub default_target: Option< String >,
+    /// The `-O` level passed to `wasm-opt` on the native wasm target.
+    pub wasm_opt_level: Option< String >,
+    /// The name the generated `.js` exports the module under on the native wasm target.
+    pub module_name: Option< String >
+}
+
+/// Commands which `cargo web start` runs around every rebuild,
+/// from the crate's root; e.g. to run an asset pipeline.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WatchConfig {
+    pub pre_build: Option< Vec< String > >,
+    pub post_build: Option< Vec< String > >
+}
+
+/// The values `wasm-opt-level` in the `[build]` table accepts.
+pub const WASM_OPT_LEVELS: &'static [&'static str] = &[ "0", "1", "2", "3", "4", "s", "z" ];
+
+const KNOWN_KEYS: &'static [&'static str] = &[
+    "link-args",
+    "build-std",
+    "build-info",
+    "wasm-opt-args",
+    "server",
+    "allocators",
+    "test",
+    "target-features",
+    "workers",
+    "allowed-imports",
+    "expected-features",
+    "sign-command",
+    "defaults",
+    "max-instantiation-ms",
+    "watch",
+    "host-imports",
+    "content-security-policy",
+    "target",
+    "emscripten",
+    "build",
+    "dev-server"
+];
+
+/// Values used when the corresponding command line flags are absent.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Defaults {
+    pub target: Option< String >,
+    pub features: Option< Vec< String > >,
+    pub release: Option< bool >,
+    pub use_system_emscripten: Option< bool >
+}
+
 pub enum Warning {
-    UnknownKey( String )
+    UnknownKey( String ),
+    /// There's both a `Web.toml` and a `[package.metadata.cargo-web]` table.
+    IgnoredManifestMetadata
+}
+
+/// Takes the `[package.metadata.cargo-web]` table out of a parsed `Cargo.toml`.
+fn metadata_from_manifest( mut manifest: toml::Value ) -> Result< Option< toml::Value >, Box< Error > > {
+    let metadata = manifest.get_mut( "package" )
+        .and_then( |package| package.get_mut( "metadata" ) )
+        .and_then( |metadata| metadata.as_table_mut() )
+        .and_then( |metadata| metadata.remove( "cargo-web" ) );
+
+    match metadata {
+        Some( metadata ) => {
+            if metadata.is_table() {
+                Ok( Some( metadata ) )
+            } else {
+                Err( "`package.metadata.cargo-web` in your `Cargo.toml` has to be a table".into() )
+            }
+        },
+        None => Ok( None )
+    }
 }
 
 impl Config {
-    pub fn load_from_file< P: AsRef< Path > >( path: P ) -> Result< Option< (Self, Vec< Warning >) >, Box< Error > > {
+    fn read_raw< P: AsRef< Path > >( path: P ) -> Result< Option< toml::Value >, Box< Error > > {
         let config_toml = match read( path ) {
             Ok( config ) => config,
             Err( error ) => {
@@ -28,34 +225,101 @@ impl Config {
             }
         };
 
-        let config = toml::from_str( config_toml.as_str() )?;
+        Ok( Some( toml::from_str( config_toml.as_str() )? ) )
+    }
+
+    fn from_raw( mut raw: toml::Value ) -> Result< (Self, Vec< Warning >), Box< Error > > {
+        let expanded_variables = RefCell::new( BTreeMap::new() );
+        expand_variables_in( &mut raw, &|name: &str| {
+            let value = env::var( name ).ok();
+            if let Some( ref value ) = value {
+                expanded_variables.borrow_mut().insert( name.to_owned(), value.clone() );
+            }
+            value
+        })?;
 
         // It seems bizzare that I have to do this manually.
-        let raw: toml::Value = toml::from_str( config_toml.as_str() )?;
         let mut warnings = Vec::new();
         match raw {
-            toml::Value::Table( table ) => {
-                for (key, _) in table {
-                    if key == "link-args" {
-                        continue;
-                    } else {
-                        warnings.push( Warning::UnknownKey( key.into() ) );
+            toml::Value::Table( ref table ) => {
+                for key in table.keys() {
+                    if !KNOWN_KEYS.contains( &key.as_str() ) {
+                        warnings.push( Warning::UnknownKey( key.clone() ) );
                     }
                 }
             },
             _ => panic!()
         }
 
-        Ok( Some( (config, warnings) ) )
+        let mut config: Config = raw.try_into()?;
+        config.expanded_variables = expanded_variables.into_inner();
+        Ok( (config, warnings) )
+    }
+
+    /// The global `link-args` followed by the ones from `[target.<triplet>]`.
+    ///
+    /// Entries containing spaces, like `-s TOTAL_MEMORY=67108864`, are split
+    /// since every chunk has to be passed to `rustc` as a separate `-C link-arg`.
+    pub fn link_args_for( &self, triplet: &str ) -> Vec< &str > {
+        let target_link_args = self.target.as_ref()
+            .and_then( |targets| targets.get( triplet ) )
+            .and_then( |target| target.link_args.as_ref() );
+
+        self.link_args.iter().chain( target_link_args )
+            .flat_map( |link_args| link_args.iter().flat_map( |arg| arg.split_whitespace() ) )
+            .collect()
     }
 
-    pub fn load_for_package( package: &CargoPackage ) -> Result< Option< (Self, Vec< Warning >) >, Box< Error > > {
+    pub fn load_from_file< P: AsRef< Path > >( path: P ) -> Result< Option< (Self, Vec< Warning >) >, Box< Error > > {
+        match Config::read_raw( path )? {
+            Some( raw ) => Ok( Some( Config::from_raw( raw )? ) ),
+            None => Ok( None )
+        }
+    }
+
+    /// Loads `Web.toml` along with the `Web.<overlay>.toml` next to it,
+    /// if there is one, whose settings take precedence.
+    ///
+    /// Without a `Web.toml` the `[package.metadata.cargo-web]` table
+    /// from the package's `Cargo.toml` is used in its place.
+    pub fn load_for_package( package: &CargoPackage, overlay: Option< &str > ) -> Result< Option< (Self, Vec< Warning >) >, Box< Error > > {
         let path = package.manifest_path.with_file_name( "Web.toml" );
-        Config::load_from_file( path )
+        let overlay_raw = match overlay {
+            Some( overlay ) => Config::read_raw( package.manifest_path.with_file_name( format!( "Web.{}.toml", overlay ) ) )?,
+            None => None
+        };
+
+        let metadata = match Config::read_raw( &package.manifest_path )? {
+            Some( manifest ) => metadata_from_manifest( manifest )?,
+            None => None
+        };
+
+        let mut warnings = Vec::new();
+        let base = match (Config::read_raw( path )?, metadata) {
+            (Some( raw ), Some( _ )) => {
+                warnings.push( Warning::IgnoredManifestMetadata );
+                Some( raw )
+            },
+            (Some( raw ), None) | (None, Some( raw )) => Some( raw ),
+            (None, None) => None
+        };
+
+        let raw = match (base, overlay_raw) {
+            (Some( mut base ), Some( overlay )) => {
+                merge( &mut base, overlay );
+                base
+            },
+            (Some( raw ), None ) | (None, Some( raw )) => raw,
+            (None, None) => return Ok( None )
+        };
+
+        let (config, raw_warnings) = Config::from_raw( raw )?;
+        warnings.extend( raw_warnings );
+        Ok( Some( (config, warnings) ) )
     }
 
-    pub fn load_for_package_printing_warnings( package: &CargoPackage ) -> Result< Option< Self >, Box< Error > > {
-        let (config, warnings) = match Config::load_for_package( package )? {
+    pub fn load_for_package_printing_warnings( package: &CargoPackage, overlay: Option< &str > ) -> Result< Option< Self >, Box< Error > > {
+        let (config, warnings) = match Config::load_for_package( package, overlay )? {
             Some( (config, warnings) ) => (config, warnings),
             None => return Ok( None )
         };
@@ -64,10 +328,327 @@ impl Config {
             match warning {
                 Warning::UnknownKey( key ) => {
                     println_err!( "warning: unknown key in Web.toml: {}", key );
+                },
+                Warning::IgnoredManifestMetadata => {
+                    println_err!( "warning: both `Web.toml` and `[package.metadata.cargo-web]` in `Cargo.toml` exist; the latter is ignored" );
                 }
             }
         }
 
         Ok( Some( config ) )
     }
+
+    /// A JSON Schema describing `Web.toml`, for editors which
+    /// can use one for completion and validation.
+    pub fn json_schema() -> Value {
+        let strings = json!({ "type": "array", "items": { "type": "string" } });
+        json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "title": "Web.toml",
+            "description": "The cargo-web configuration file",
+            "type": "object",
+            "properties": {
+                "link-args": strings,
+                "build-std": {
+                    "type": "object",
+                    "properties": {
+                        "crates": strings,
+                        "features": strings
+                    },
+                    "additionalProperties": false
+                },
+                "build-info": { "type": "boolean" },
+                "wasm-opt-args": strings,
+                "server": {
+                    "type": "object",
+                    "properties": {
+                        "mime": { "type": "object", "additionalProperties": { "type": "string" } },
+                        "not-found-page": { "type": "string" }
+                    },
+                    "additionalProperties": false
+                },
+                "allocators": { "type": "object", "additionalProperties": { "type": "string" } },
+                "test": {
+                    "type": "object",
+                    "properties": {
+                        "chromium-launch-retries": { "type": "integer", "minimum": 0 },
+                        "timeout": { "type": "integer", "minimum": 1 }
+                    },
+                    "additionalProperties": false
+                },
+                "target-features": { "type": "object", "additionalProperties": strings },
+                "workers": strings,
+                "allowed-imports": strings,
+                "expected-features": { "type": "object", "additionalProperties": strings },
+                "sign-command": strings,
+                "defaults": {
+                    "type": "object",
+                    "properties": {
+                        "target": { "enum": [ "asmjs-unknown-emscripten", "wasm32-unknown-emscripten", "wasm32-unknown-unknown" ] },
+                        "features": strings,
+                        "release": { "type": "boolean" },
+                        "use-system-emscripten": { "type": "boolean" }
+                    },
+                    "additionalProperties": false
+                },
+                "max-instantiation-ms": { "type": "integer", "minimum": 0 },
+                "watch": {
+                    "type": "object",
+                    "properties": {
+                        "pre-build": strings,
+                        "post-build": strings
+                    },
+                    "additionalProperties": false
+                },
+                "host-imports": { "type": "string" },
+                "content-security-policy": { "type": [ "boolean", "string" ] },
+                "target": {
+                    "type": "object",
+                    "additionalProperties": {
+                        "type": "object",
+                        "properties": {
+                            "link-args": strings
+                        },
+                        "additionalProperties": false
+                    }
+                },
+                "emscripten": {
+                    "type": "object",
+                    "properties": {
+                        "version": { "type": "string" },
+                        "strict": { "type": "boolean" }
+                    },
+                    "additionalProperties": false
+                },
+                "build": {
+                    "type": "object",
+                    "properties": {
+                        "default-target": {
+                            "description": "Deprecated; use `target` in `[defaults]` instead",
+                            "enum": [ "asmjs-unknown-emscripten", "wasm32-unknown-emscripten", "wasm32-unknown-unknown" ]
+                        },
+                        "wasm-opt-level": { "enum": WASM_OPT_LEVELS },
+                        "module-name": { "type": "string" }
+                    },
+                    "additionalProperties": false
+                },
+                "dev-server": {
+                    "type": "object",
+                    "properties": {
+                        "headers": { "type": "object", "additionalProperties": { "type": "string" } },
+                        "index": { "type": "string" }
+                    },
+                    "additionalProperties": false
+                }
+            },
+            "additionalProperties": false
+        })
+    }
+}
+
+// Expands `${VAR}` with the value of the given variable; `$$` stands for a literal `$`.
+fn expand_variables< F >( value: &str, lookup: &F ) -> Result< String, String >
+    where F: Fn( &str ) -> Option< String >
+{
+    let mut output = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some( ch ) = chars.next() {
+        if ch != '$' {
+            output.push( ch );
+            continue;
+        }
+
+        match chars.peek().cloned() {
+            Some( '$' ) => {
+                chars.next();
+                output.push( '$' );
+            },
+            Some( '{' ) => {
+                chars.next();
+                let mut name = String::new();
+                let mut is_terminated = false;
+                while let Some( ch ) = chars.next() {
+                    if ch == '}' {
+                        is_terminated = true;
+                        break;
+                    }
+                    name.push( ch );
+                }
+
+                if !is_terminated {
+                    return Err( format!( "unterminated `${{` in `{}` in your `Web.toml`", value ) );
+                }
+
+                match lookup( &name ) {
+                    Some( expanded ) => output.push_str( &expanded ),
+                    None => return Err( format!( "the environment variable `{}` which your `Web.toml` refers to isn't set", name ) )
+                }
+            },
+            _ => output.push( '$' )
+        }
+    }
+
+    Ok( output )
+}
+
+fn expand_variables_in< F >( value: &mut toml::Value, lookup: &F ) -> Result< (), String >
+    where F: Fn( &str ) -> Option< String >
+{
+    match *value {
+        toml::Value::String( ref mut string ) => {
+            let expanded = expand_variables( string, lookup )?;
+            *string = expanded;
+        },
+        toml::Value::Array( ref mut values ) => {
+            for value in values {
+                expand_variables_in( value, lookup )?;
+            }
+        },
+        toml::Value::Table( ref mut table ) => {
+            for (_, value) in table.iter_mut() {
+                expand_variables_in( value, lookup )?;
+            }
+        },
+        _ => {}
+    }
+
+    Ok(())
+}
+
+// Tables are merged key by key; everything else, arrays included, is replaced.
+fn merge( base: &mut toml::Value, overlay: toml::Value ) {
+    match (base, overlay) {
+        (&mut toml::Value::Table( ref mut base ), toml::Value::Table( overlay )) => {
+            for (key, value) in overlay {
+                match base.get_mut( &key ) {
+                    Some( existing ) => {
+                        merge( existing, value );
+                        continue;
+                    },
+                    None => {}
+                }
+
+                base.insert( key, value );
+            }
+        },
+        (base, overlay) => *base = overlay
+    }
+}
+
+#[test]
+fn test_merge() {
+    let mut base: toml::Value = toml::from_str( "link-args = [\"-s\"]\n[defaults]\nrelease = true\nfeatures = [\"a\"]\n" ).unwrap();
+    let overlay: toml::Value = toml::from_str( "link-args = [\"-g\"]\n[defaults]\nfeatures = [\"b\"]\n" ).unwrap();
+    merge( &mut base, overlay );
+
+    let config: Config = base.try_into().unwrap();
+    assert_eq!( config.link_args, Some( vec![ "-g".to_owned() ] ) );
+    let defaults = config.defaults.unwrap();
+    assert_eq!( defaults.release, Some( true ) );
+    assert_eq!( defaults.features, Some( vec![ "b".to_owned() ] ) );
+}
+
+#[test]
+fn test_link_args_for() {
+    let config: Config = toml::from_str( "link-args = [\"-s\", \"TOTAL_MEMORY=64MB\"]\n[target.wasm32-unknown-emscripten]\nlink-args = [\"-g4\"]\n" ).unwrap();
+    assert_eq!( config.link_args_for( "wasm32-unknown-emscripten" ), vec![ "-s", "TOTAL_MEMORY=64MB", "-g4" ] );
+    assert_eq!( config.link_args_for( "asmjs-unknown-emscripten" ), vec![ "-s", "TOTAL_MEMORY=64MB" ] );
+}
+
+#[test]
+fn test_link_args_with_spaces_are_split() {
+    let config: Config = toml::from_str( "link-args = [\"-s TOTAL_MEMORY=67108864\", \"--js-opts  0\"]\n" ).unwrap();
+    assert_eq!( config.link_args_for( "asmjs-unknown-emscripten" ), vec![ "-s", "TOTAL_MEMORY=67108864", "--js-opts", "0" ] );
+}
+
+#[test]
+fn test_json_schema_covers_every_key() {
+    let schema = Config::json_schema();
+    let mut properties: Vec< &str > = schema[ "properties" ].as_object().unwrap().keys().map( |key| key.as_str() ).collect();
+    let mut known_keys = KNOWN_KEYS.to_vec();
+    properties.sort();
+    known_keys.sort();
+    assert_eq!( properties, known_keys );
+}
+
+// Makes up a value which the `schema` accepts, using every property it lists.
+#[cfg(test)]
+fn example_for_schema( schema: &Value ) -> Value {
+    if let Some( values ) = schema.get( "enum" ) {
+        return values[ 0 ].clone();
+    }
+
+    let ty = match schema[ "type" ] {
+        Value::Array( ref types ) => types[ 0 ].as_str().unwrap(),
+        ref ty => ty.as_str().unwrap()
+    };
+
+    match ty {
+        "string" => json!( "example" ),
+        "boolean" => json!( true ),
+        "integer" => json!( 1 ),
+        "array" => json!([ example_for_schema( &schema[ "items" ] ) ]),
+        "object" => {
+            let mut object = ::serde_json::Map::new();
+            if let Some( properties ) = schema.get( "properties" ).and_then( |properties| properties.as_object() ) {
+                for (key, property) in properties {
+                    object.insert( key.clone(), example_for_schema( property ) );
+                }
+            }
+
+            if let Some( values ) = schema.get( "additionalProperties" ).filter( |values| values.is_object() ) {
+                object.insert( "key".to_owned(), example_for_schema( values ) );
+            }
+
+            Value::Object( object )
+        },
+        ty => panic!( "unhandled type in the schema: {}", ty )
+    }
+}
+
+#[cfg(test)]
+fn json_to_toml( value: Value ) -> toml::Value {
+    match value {
+        Value::String( string ) => toml::Value::String( string ),
+        Value::Bool( boolean ) => toml::Value::Boolean( boolean ),
+        Value::Number( number ) => toml::Value::Integer( number.as_i64().unwrap() ),
+        Value::Array( values ) => toml::Value::Array( values.into_iter().map( json_to_toml ).collect() ),
+        Value::Object( object ) => toml::Value::Table( object.into_iter().map( |(key, value)| (key, json_to_toml( value )) ).collect() ),
+        Value::Null => panic!( "TOML doesn't have a null" )
+    }
+}
+
+// Unknown keys are silently ignored when deserializing, so the example
+// is serialized back to make sure that every one of them was picked up.
+#[test]
+fn test_json_schema_matches_config() {
+    let example = json_to_toml( example_for_schema( &Config::json_schema() ) );
+    let config: Config = example.clone().try_into().unwrap();
+    assert_eq!( toml::Value::try_from( &config ).unwrap(), example );
+}
+
+#[test]
+fn test_expand_variables() {
+    let lookup = |name: &str| if name == "PREFIX" { Some( "/opt/web".to_owned() ) } else { None };
+    assert_eq!( expand_variables( "-L${PREFIX}/lib", &lookup ), Ok( "-L/opt/web/lib".to_owned() ) );
+    assert_eq!( expand_variables( "cost: $$5 or $5", &lookup ), Ok( "cost: $5 or $5".to_owned() ) );
+    assert_eq!( expand_variables( "$${PREFIX}", &lookup ), Ok( "${PREFIX}".to_owned() ) );
+
+    let error = expand_variables( "-L${MISSING}/lib", &lookup ).unwrap_err();
+    assert!( error.contains( "`MISSING`" ) );
+    assert!( expand_variables( "${PREFIX", &lookup ).is_err() );
+}
+
+#[test]
+fn test_metadata_from_manifest() {
+    let manifest: toml::Value = toml::from_str( "[package]\nname = \"foo\"\n[package.metadata.cargo-web]\nlink-args = [\"-g\"]\n" ).unwrap();
+    let metadata = metadata_from_manifest( manifest ).unwrap().unwrap();
+    let (config, _) = Config::from_raw( metadata ).unwrap();
+    assert_eq!( config.link_args, Some( vec![ "-g".to_owned() ] ) );
+
+    let manifest: toml::Value = toml::from_str( "[package]\nname = \"foo\"\n" ).unwrap();
+    assert!( metadata_from_manifest( manifest ).unwrap().is_none() );
+
+    let manifest: toml::Value = toml::from_str( "[package.metadata]\ncargo-web = 1\n" ).unwrap();
+    assert!( metadata_from_manifest( manifest ).is_err() );
 }
@@ -1,104 +1,251 @@
-use std::process::exit;
+use std::process::{Command, exit};
 use std::path::{Path, PathBuf};
+use std::fs;
+use std::env;
+
+use regex::Regex;
 
 use package::{
     PrebuiltPackage,
-    download_package
+    download_package,
+    package_root
+};
+use utils::{
+    check_if_command_exists,
+    read,
+    write
 };
-use utils::check_if_command_exists;
-
-fn emscripten_package() -> Option< PrebuiltPackage > {
-    let package =
-        if cfg!( target_os = "linux" ) && cfg!( target_arch = "x86_64" ) {
-            PrebuiltPackage {
-                url: "https://github.com/koute/emscripten-build/releases/download/emscripten-1.37.27-1/emscripten-1.37.27-1-x86_64-unknown-linux-gnu.tgz",
-                name: "emscripten",
-                version: "1.37.27-1",
+use error::Error;
+
+struct PrebuiltArchive {
+    arch: &'static str,
+    hash: &'static str,
+    size: u64
+}
+
+struct KnownVersion {
+    version: &'static str,
+    emscripten: &'static [PrebuiltArchive],
+    binaryen: &'static [PrebuiltArchive]
+}
+
+/// The versions of the prebuilt Emscripten SDK which we know the checksums of, oldest first;
+/// supporting a new one only needs another entry here.
+const KNOWN_VERSIONS: &'static [KnownVersion] = &[
+    KnownVersion {
+        version: "1.37.27-1",
+        emscripten: &[
+            PrebuiltArchive {
                 arch: "x86_64-unknown-linux-gnu",
                 hash: "43e653d26bfe95b010267538949e2d0cb23364571972042165d0258d55e8ca66",
                 size: 136902444
-            }
-        } else if cfg!( target_os = "linux" ) && cfg!( target_arch = "x86" ) {
-            PrebuiltPackage {
-                url: "https://github.com/koute/emscripten-build/releases/download/emscripten-1.37.27-1/emscripten-1.37.27-1-i686-unknown-linux-gnu.tgz",
-                name: "emscripten",
-                version: "1.37.27-1",
+            },
+            PrebuiltArchive {
                 arch: "i686-unknown-linux-gnu",
                 hash: "a3a1e4622f4509b903eaf76c3b1c7fc981f656185f3fcb7cd8a81718d0e11bb3",
                 size: 144527242
             }
-        } else {
-            return None;
-        };
-
-    Some( package )
-}
-
-fn binaryen_package() -> Option< PrebuiltPackage > {
-    let package =
-        if cfg!( target_os = "linux" ) && cfg!( target_arch = "x86_64" ) {
-            PrebuiltPackage {
-                url: "https://github.com/koute/emscripten-build/releases/download/emscripten-1.37.27-1/binaryen-1.37.27-1-x86_64-unknown-linux-gnu.tgz",
-                name: "binaryen",
-                version: "1.37.27-1",
+        ],
+        binaryen: &[
+            PrebuiltArchive {
                 arch: "x86_64-unknown-linux-gnu",
                 hash: "aa46c2d3d6031481a88c45e072acb1c625fbc22aae8a5271fd70f5b879666c1a",
                 size: 12625100
-            }
-        } else if cfg!( target_os = "linux" ) && cfg!( target_arch = "x86" ) {
-            PrebuiltPackage {
-                url: "https://github.com/koute/emscripten-build/releases/download/emscripten-1.37.27-1/binaryen-1.37.27-1-i686-unknown-linux-gnu.tgz",
-                name: "binaryen",
-                version: "1.37.27-1",
+            },
+            PrebuiltArchive {
                 arch: "i686-unknown-linux-gnu",
                 hash: "2a3eff1a7bbb5f5e4bceb0da1ebd508c6458d1dfe7f511641668db4d96b98d8a",
                 size: 12706642
             }
-        } else {
-            return None;
-        };
+        ]
+    }
+];
 
-    Some( package )
+pub fn known_versions() -> Vec< &'static str > {
+    KNOWN_VERSIONS.iter().map( |known| known.version ).collect()
 }
 
-fn check_emscripten() {
-    let binary = if cfg!( windows ) {
+/// Finds the known version which matches `version`, which can
+/// also omit the revision of the prebuilt package, e.g. `1.37.27`.
+pub fn find_known_version( version: &str ) -> Option< &'static str > {
+    KNOWN_VERSIONS.iter().map( |known| known.version ).find( |known| {
+        *known == version || (known.starts_with( version ) && known[ version.len().. ].starts_with( '-' ))
+    })
+}
+
+pub fn latest_version() -> &'static str {
+    KNOWN_VERSIONS[ KNOWN_VERSIONS.len() - 1 ].version
+}
+
+fn default_version_path() -> PathBuf {
+    package_root( "emscripten" ).join( "default-version" )
+}
+
+/// The version set with `cargo web emscripten update --set-default`,
+/// or the latest one if none was set.
+pub fn default_version() -> &'static str {
+    let version = match read( default_version_path() ) {
+        Ok( version ) => version,
+        Err( _ ) => return latest_version()
+    };
+
+    let version = version.trim();
+    match KNOWN_VERSIONS.iter().find( |known| known.version == version ) {
+        Some( known ) => known.version,
+        None => {
+            println_err!( "warning: the default Emscripten version `{}` is unknown; using `{}` instead", version, latest_version() );
+            latest_version()
+        }
+    }
+}
+
+pub fn set_default_version( version: &str ) -> Result< (), Error > {
+    let path = default_version_path();
+    fs::create_dir_all( path.parent().unwrap() )
+        .and_then( |_| write( &path, version ) )
+        .map_err( |err| Error::RuntimeError( format!( "cannot write the default Emscripten version to {:?}", path ), err.into() ) )
+}
+
+// The architecture of the prebuilt packages which can run on this machine.
+fn host_arch() -> Option< &'static str > {
+    if cfg!( target_os = "linux" ) && cfg!( target_arch = "x86_64" ) {
+        Some( "x86_64-unknown-linux-gnu" )
+    } else if cfg!( target_os = "linux" ) && cfg!( target_arch = "x86" ) {
+        Some( "i686-unknown-linux-gnu" )
+    } else {
+        None
+    }
+}
+
+fn prebuilt_package( name: &'static str, version: &str, arch: &str ) -> Option< PrebuiltPackage > {
+    let known = KNOWN_VERSIONS.iter().find( |known| known.version == version )?;
+    let archives = if name == "emscripten" { known.emscripten } else { known.binaryen };
+    let archive = archives.iter().find( |archive| archive.arch == arch )?;
+
+    Some( PrebuiltPackage {
+        url: format!(
+            "https://github.com/koute/emscripten-build/releases/download/emscripten-{version}/{name}-{version}-{arch}.tgz",
+            version = known.version,
+            name = name,
+            arch = archive.arch
+        ),
+        name,
+        version: known.version,
+        arch: archive.arch,
+        hash: archive.hash,
+        size: archive.size
+    })
+}
+
+pub fn emscripten_package( version: &str ) -> Option< PrebuiltPackage > {
+    prebuilt_package( "emscripten", version, host_arch()? )
+}
+
+pub fn binaryen_package( version: &str ) -> Option< PrebuiltPackage > {
+    prebuilt_package( "binaryen", version, host_arch()? )
+}
+
+// This is where Emscripten keeps the system libraries it has built.
+fn emscripten_cache_path() -> Option< PathBuf > {
+    env::var_os( "EM_CACHE" )
+        .map( PathBuf::from )
+        .or_else( || env::home_dir().map( |home| home.join( ".emscripten_cache" ) ) )
+}
+
+// The prebuilt versions each get their own cache, since the libraries
+// built by one version aren't necessarily compatible with another.
+fn versioned_cache_root() -> PathBuf {
+    package_root( "emscripten-cache" )
+}
+
+/// Removes the caches of the prebuilt Emscripten so that it'll rebuild its
+/// system libraries; the `EM_CACHE` used by the system's Emscripten is
+/// only removed when `include_global` is set, since it's not ours.
+pub fn clear_emscripten_cache( include_global: bool ) -> Result< (), Error > {
+    let global_path = if include_global { emscripten_cache_path() } else { None };
+    for path in global_path.into_iter().chain( Some( versioned_cache_root() ) ) {
+        if path.exists() {
+            println_err!( "Removing Emscripten's cache at {:?}...", path );
+            fs::remove_dir_all( &path )
+                .map_err( |err| Error::RuntimeError( format!( "cannot remove Emscripten's cache at {:?}", path ), err.into() ) )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_emcc_version( output: &str ) -> Option< &str > {
+    let regex = Regex::new( r"(\d+\.\d+\.\d+)" ).unwrap();
+    let first_line = output.lines().next()?;
+    regex.captures( first_line ).map( |captures| captures.get( 1 ).unwrap().as_str() )
+}
+
+fn system_emscripten_version() -> Option< String > {
+    let output = Command::new( emcc_binary() ).arg( "--version" ).output().ok()?;
+    let stdout = String::from_utf8_lossy( &output.stdout );
+    parse_emcc_version( &stdout ).map( |version| version.to_owned() )
+}
+
+// A mismatch is only an error in the `strict_version` mode.
+fn version_mismatch( message: String, strict_version: bool ) -> Result< (), Error > {
+    if strict_version {
+        Err( Error::ConfigurationError( message ) )
+    } else {
+        println_err!( "warning: {}", message );
+        Ok(())
+    }
+}
+
+fn check_system_emscripten_version( pinned_version: &str, strict_version: bool ) -> Result< (), Error > {
+    // The system's Emscripten doesn't have a revision like our prebuilt packages.
+    let pinned_version = pinned_version.split( '-' ).next().unwrap();
+    match system_emscripten_version() {
+        Some( ref version ) if version == pinned_version => Ok(()),
+        Some( version ) => version_mismatch( format!( "your `Web.toml` pins Emscripten {} but {} is installed", pinned_version, version ), strict_version ),
+        None => version_mismatch( format!( "your `Web.toml` pins Emscripten {} but the installed version couldn't be determined", pinned_version ), strict_version )
+    }
+}
+
+fn emcc_binary() -> &'static str {
+    if cfg!( windows ) {
         "emcc.bat"
     } else {
         "emcc"
-    };
+    }
+}
 
-    if check_if_command_exists( binary, None ) {
+fn check_emscripten() {
+    if check_if_command_exists( emcc_binary(), None ) {
         return;
     }
 
     println_err!( "error: you don't have Emscripten installed!" );
-    println_err!( "" );
+    println_err_cont!( "" );
 
     if Path::new( "/usr/bin/pacman" ).exists() {
-        println_err!( "You can most likely install it like this:" );
-        println_err!( "  sudo pacman -S emscripten" );
+        println_err_cont!( "You can most likely install it like this:" );
+        println_err_cont!( "  sudo pacman -S emscripten" );
     } else if Path::new( "/usr/bin/apt-get" ).exists() {
-        println_err!( "You can most likely install it like this:" );
-        println_err!( "  sudo apt-get install emscripten" );
+        println_err_cont!( "You can most likely install it like this:" );
+        println_err_cont!( "  sudo apt-get install emscripten" );
     } else if cfg!( target_os = "linux" ) {
-        println_err!( "You can most likely find it in your distro's repositories." );
+        println_err_cont!( "You can most likely find it in your distro's repositories." );
     } else if cfg!( target_os = "windows" ) {
-        println_err!( "Download and install emscripten from the official site: http://kripken.github.io/emscripten-site/docs/getting_started/downloads.html" );
+        println_err_cont!( "Download and install emscripten from the official site: http://kripken.github.io/emscripten-site/docs/getting_started/downloads.html" );
     }
 
     if cfg!( unix ) {
         if cfg!( target_os = "linux" ) {
-            println_err!( "If not you can install it manually like this:" );
+            println_err_cont!( "If not you can install it manually like this:" );
         } else {
-            println_err!( "You can install it manually like this:" );
+            println_err_cont!( "You can install it manually like this:" );
         }
-        println_err!( "  curl -O https://s3.amazonaws.com/mozilla-games/emscripten/releases/emsdk-portable.tar.gz" );
-        println_err!( "  tar -xzf emsdk-portable.tar.gz" );
-        println_err!( "  source emsdk_portable/emsdk_env.sh" );
-        println_err!( "  emsdk update" );
-        println_err!( "  emsdk install sdk-incoming-64bit" );
-        println_err!( "  emsdk activate sdk-incoming-64bit" );
+        println_err_cont!( "  curl -O https://s3.amazonaws.com/mozilla-games/emscripten/releases/emsdk-portable.tar.gz" );
+        println_err_cont!( "  tar -xzf emsdk-portable.tar.gz" );
+        println_err_cont!( "  source emsdk_portable/emsdk_env.sh" );
+        println_err_cont!( "  emsdk update" );
+        println_err_cont!( "  emsdk install sdk-incoming-64bit" );
+        println_err_cont!( "  emsdk activate sdk-incoming-64bit" );
     }
 
     exit( 101 );
@@ -107,33 +254,76 @@ fn check_emscripten() {
 pub struct Emscripten {
     pub binaryen_path: Option< PathBuf >,
     pub emscripten_path: PathBuf,
-    pub emscripten_llvm_path: PathBuf
+    pub emscripten_llvm_path: PathBuf,
+    pub cache_path: PathBuf
 }
 
+/// Returns `None` when the system's Emscripten should be used.
+///
+/// The `pinned_version` comes from the `[emscripten]` table in `Web.toml`;
+/// when the version it names can't be used that's only a warning, unless `strict_version` is set.
+///
+/// A system Emscripten which is missing or doesn't work is replaced with the
+/// prebuilt one, unless `no_fallback` is set, in which case it's an error.
 pub fn initialize_emscripten(
     use_system_emscripten: bool,
-    targeting_webasm: bool
-) -> Option< Emscripten > {
+    no_fallback: bool,
+    targeting_webasm: bool,
+    pinned_version: Option< &str >,
+    strict_version: bool
+) -> Result< Option< Emscripten >, Error > {
 
     if use_system_emscripten {
-        check_emscripten();
-        return None;
+        let problem = if !check_if_command_exists( emcc_binary(), None ) {
+            "the system's Emscripten isn't installed since `emcc` couldn't be found"
+        } else if system_emscripten_version().is_none() {
+            "the system's Emscripten seems to be broken since `emcc --version` doesn't report its version"
+        } else {
+            if let Some( pinned_version ) = pinned_version {
+                check_system_emscripten_version( pinned_version, strict_version )?;
+            }
+            return Ok( None );
+        };
+
+        if no_fallback {
+            return Err( Error::EnvironmentError( format!( "{}; drop `--strict` to fall back to the prebuilt one", problem ) ) );
+        }
+
+        println_err!( "warning: {}; falling back to the prebuilt one", problem );
     }
 
-    let emscripten_package = match emscripten_package() {
+    let version = match pinned_version {
+        Some( pinned_version ) => match find_known_version( pinned_version ) {
+            Some( version ) => version,
+            None => {
+                let message = format!( "your `Web.toml` pins Emscripten {} which isn't available prebuilt; using {} instead", pinned_version, default_version() );
+                version_mismatch( message, strict_version )?;
+                default_version()
+            }
+        },
+        None => default_version()
+    };
+
+    let emscripten_package = match emscripten_package( version ) {
         Some( pkg ) => pkg,
         None => {
             check_emscripten();
-            return None;
+            if let Some( pinned_version ) = pinned_version {
+                check_system_emscripten_version( pinned_version, strict_version )?;
+            }
+            return Ok( None );
         }
     };
 
     let binaryen_package = if targeting_webasm {
-        match binaryen_package() {
+        match binaryen_package( version ) {
             Some( pkg ) => Some( pkg ),
             None => {
                 check_emscripten();
-                return None;
+                if let Some( pinned_version ) = pinned_version {
+                    check_system_emscripten_version( pinned_version, strict_version )?;
+                }
+                return Ok( None );
             }
         }
     } else {
@@ -151,9 +341,27 @@ pub fn initialize_emscripten(
         None
     };
 
-    Some( Emscripten {
+    Ok( Some( Emscripten {
         binaryen_path,
         emscripten_path,
-        emscripten_llvm_path
-    })
+        emscripten_llvm_path,
+        cache_path: versioned_cache_root().join( version )
+    }))
+}
+
+#[test]
+fn test_parse_emcc_version() {
+    let output = "emcc (Emscripten gcc/clang-like replacement) 1.37.27 (commit 1a2b3c4)\nCopyright (C) 2014 the Emscripten authors\n";
+    assert_eq!( parse_emcc_version( output ), Some( "1.37.27" ) );
+    assert_eq!( find_known_version( "1.37.27" ), Some( "1.37.27-1" ) );
+    assert_eq!( find_known_version( "1.37.2" ), None );
+}
+
+#[test]
+fn test_prebuilt_package() {
+    let package = prebuilt_package( "binaryen", "1.37.27-1", "i686-unknown-linux-gnu" ).unwrap();
+    assert_eq!( package.url, "https://github.com/koute/emscripten-build/releases/download/emscripten-1.37.27-1/binaryen-1.37.27-1-i686-unknown-linux-gnu.tgz" );
+    assert_eq!( package.hash, "2a3eff1a7bbb5f5e4bceb0da1ebd508c6458d1dfe7f511641668db4d96b98d8a" );
+    assert!( prebuilt_package( "emscripten", "1.37.27-1", "x86_64-apple-darwin" ).is_none() );
+    assert!( prebuilt_package( "emscripten", "1.37.26-1", "x86_64-unknown-linux-gnu" ).is_none() );
 }
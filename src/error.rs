@@ -6,7 +6,9 @@ pub enum Error {
     ConfigurationError( String ),
     EnvironmentError( String ),
     RuntimeError( String, Box< error::Error > ),
-    BuildError
+    BuildError,
+    /// The build didn't produce an artifact which was needed.
+    NoArtifact( String )
 }
 
 impl error::Error for Error {
@@ -15,7 +17,8 @@ impl error::Error for Error {
             Error::ConfigurationError( ref message ) => &message,
             Error::EnvironmentError( ref message ) => &message,
             Error::RuntimeError( ref message, _ ) => &message,
-            Error::BuildError => "build failed"
+            Error::BuildError => "build failed",
+            Error::NoArtifact( ref message ) => &message
         }
     }
 }
@@ -40,14 +40,19 @@ extern crate cargo_metadata;
 extern crate ansi_term;
 
 extern crate semver;
+extern crate atty;
+#[cfg(unix)]
+extern crate libc;
 
 use std::process::exit;
 use std::env;
+use std::time::Duration;
 
 use clap::{
     Arg,
     App,
     AppSettings,
+    ArgMatches,
     SubCommand
 };
 
@@ -66,15 +71,28 @@ mod wasm_inline_js;
 mod wasm_export_main;
 mod wasm_export_table;
 mod wasm_hook_grow;
+mod wasm_normalize;
+mod wasm_analyze_deps;
+mod wasm_size;
 mod wasm_runtime;
+mod wasm_typescript;
 mod wasm_context;
 mod wasm_intrinsics;
 mod emscripten;
 mod test_chromium;
+mod test_firefox;
+mod watchdog;
 mod chrome_devtools;
 mod cmd_build;
+mod cmd_check;
 mod cmd_start;
 mod cmd_test;
+mod cmd_bench;
+mod cmd_repl;
+mod cmd_deploy;
+mod cmd_verify;
+mod cmd_config;
+mod cmd_emscripten;
 
 fn add_shared_build_params< 'a, 'b >( app: App< 'a, 'b > ) -> App< 'a, 'b > {
     return app
@@ -106,39 +124,164 @@ fn add_shared_build_params< 'a, 'b >( app: App< 'a, 'b > ) -> App< 'a, 'b > {
                 .long( "no-default-features" )
                 .help( "Do not build the `default` feature" )
         )
+        .arg(
+            Arg::with_name( "allocator" )
+                .long( "allocator" )
+                .help( "Enables the feature which the `[allocators]` table in `Web.toml` maps this allocator to" )
+                .value_name( "NAME" )
+                .takes_value( true )
+                .conflicts_with( "all-features" )
+        )
+        .arg(
+            Arg::with_name( "locked-features" )
+                .long( "locked-features" )
+                .help( "Fails the build if any crate gets a feature activated which `[expected-features]` in `Web.toml` doesn't list for it" )
+        )
+        .arg(
+            Arg::with_name( "sanitizer" )
+                .long( "sanitizer" )
+                .help( "Links in one of Emscripten's sanitizers (Emscripten targets only)" )
+                .value_name( "NAME" )
+                .takes_value( true )
+                .possible_values( &[
+                    "address",
+                    "undefined"
+                ])
+        )
         .arg(
             Arg::with_name( "use-system-emscripten" )
                 .long( "use-system-emscripten" )
-                .help( "Won't try to download Emscripten; will always use the system one" )
+                .help( "Won't try to download Emscripten; will always use the system one (can also be set with `CARGO_WEB_USE_SYSTEM_EMSCRIPTEN=1`)" )
+        )
+        .arg(
+            Arg::with_name( "strict" )
+                .long( "strict" )
+                .help( "Fail if the system Emscripten is missing or broken instead of falling back to the prebuilt one" )
+        )
+        .arg(
+            Arg::with_name( "no-emscripten-cache" )
+                .long( "no-emscripten-cache" )
+                .help( "Clears the prebuilt Emscripten's cache of system libraries before building, so that they get rebuilt (Emscripten targets only)" )
         )
         .arg(
             Arg::with_name( "release" )
                 .long( "release" )
                 .help( "Build artifacts in release mode, with optimizations" )
         )
+        .arg(
+            Arg::with_name( "keep-debug" )
+                .long( "keep-debug" )
+                .help( "Keep the debug info even in release builds, e.g. for profiling; this makes the `.wasm` files significantly bigger. For the native wasm target only the function names are kept, since the DWARF sections would be invalidated by the post-processing" )
+        )
         .arg(
             Arg::with_name( "target-asmjs-emscripten" )
                 .long( "target-asmjs-emscripten" )
                 .help( "Generate asmjs through Emscripten (default)" )
-                .overrides_with_all( &["target-webasm-emscripten", "target-webasm"] )
         )
         .arg(
             Arg::with_name( "target-webasm-emscripten" )
                 .long( "target-webasm-emscripten" )
                 .help( "Generate webasm through Emscripten" )
-                .overrides_with_all( &["target-asmjs-emscripten", "target-webasm"] )
         )
         .arg(
             Arg::with_name( "target-webasm" )
                 .long( "target-webasm" )
                 .help( "Generates webasm through Rust's native backend (HIGHLY EXPERIMENTAL!)" )
-                .overrides_with_all( &["target-asmjs-emscripten", "target-webasm-emscripten"] )
+        )
+        .arg(
+            Arg::with_name( "normalize" )
+                .long( "normalize" )
+                .help( "Sorts the imports and exports of the generated `.wasm` file so that it's easier to diff (native wasm target only)" )
+        )
+        .arg(
+            Arg::with_name( "typescript" )
+                .long( "typescript" )
+                .help( "Generates a `.d.ts` file declaring the functions exported from the `.wasm` file (native wasm target only)" )
+        )
+        .arg(
+            Arg::with_name( "build-heartbeat" )
+                .long( "build-heartbeat" )
+                .help( "Print a progress line every N seconds while building; 0 disables it (default: 60 when stdout is not a terminal; never with `--message-format json`)" )
+                .value_name( "SECONDS" )
+                .takes_value( true )
+                .validator( |value| value.parse::< u64 >().map( |_| () ).map_err( |_| "expected a number of seconds".to_owned() ) )
+        )
+        .arg(
+            Arg::with_name( "sarif" )
+                .long( "sarif" )
+                .help( "Write the compiler's diagnostics as a SARIF report to the given file" )
+                .value_name( "PATH" )
+                .takes_value( true )
+        )
+        .arg(
+            Arg::with_name( "unstable-flags" )
+                .short( "Z" )
+                .help( "Unstable (nightly-only) flags to pass to Cargo" )
+                .value_name( "FLAG" )
+                .takes_value( true )
+                .multiple( true )
+                .number_of_values( 1 )
+        )
+        .arg(
+            Arg::with_name( "jobs" )
+                .short( "j" )
+                .long( "jobs" )
+                .help( "Number of parallel jobs cargo should use; defaults to the number of CPUs" )
+                .value_name( "N" )
+                .takes_value( true )
+        )
+        .arg(
+            Arg::with_name( "locked" )
+                .long( "locked" )
+                .help( "Require `Cargo.lock` to be up to date; passed through to cargo" )
+        )
+        .arg(
+            Arg::with_name( "frozen" )
+                .long( "frozen" )
+                .help( "Require `Cargo.lock` and the cache to be up to date, without accessing the network; passed through to cargo" )
+        )
+        .arg(
+            Arg::with_name( "target-dir" )
+                .long( "target-dir" )
+                .help( "Directory for all generated artifacts; can also be set with `CARGO_TARGET_DIR`" )
+                .value_name( "PATH" )
+                .takes_value( true )
+        )
+        .arg(
+            Arg::with_name( "cargo-arg" )
+                .long( "cargo-arg" )
+                .help( "An extra argument to pass to Cargo as-is; can be given multiple times" )
+                .value_name( "ARG" )
+                .takes_value( true )
+                .multiple( true )
+                .number_of_values( 1 )
+                .allow_hyphen_values( true )
+        )
+        .arg(
+            Arg::with_name( "color" )
+                .long( "color" )
+                .help( "Coloring of the compiler's messages (default: auto, which colors them only when printing to a terminal)" )
+                .value_name( "WHEN" )
+                .takes_value( true )
+                .possible_values( &[
+                    "auto",
+                    "always",
+                    "never"
+                ])
         )
         .arg(
             Arg::with_name( "verbose" )
                 .short( "v" )
                 .long( "verbose" )
-                .help( "Use verbose output" )
+                .help( "Use verbose output; pass it twice to also print how cargo is going to be invoked" )
+                .multiple( true )
+        )
+        .arg(
+            Arg::with_name( "quiet" )
+                .short( "q" )
+                .long( "quiet" )
+                .help( "Don't print cargo-web's own warnings and informational messages; errors are still printed" )
+                .conflicts_with( "verbose" )
         );
 }
 
@@ -171,6 +314,12 @@ fn main() {
     let mut build_subcommand =
         SubCommand::with_name( "build" )
             .about( "Compile a local package and all of its dependencies" )
+            .arg(
+                Arg::with_name( "workspace" )
+                    .long( "workspace" )
+                    .help( "Build every package in the workspace which has a library or a binary, each with the settings from its own `Web.toml`" )
+                    .conflicts_with_all( &[ "package", "lib", "bin", "bins", "example", "examples", "test", "bench" ] )
+            )
             .arg(
                 Arg::with_name( "lib" )
                     .long( "lib" )
@@ -179,9 +328,16 @@ fn main() {
             .arg(
                 Arg::with_name( "bin" )
                     .long( "bin" )
-                    .help( "Build only the specified binary" )
+                    .help( "Build only the specified binary; can be given multiple times" )
                     .value_name( "NAME" )
                     .takes_value( true )
+                    .multiple( true )
+                    .number_of_values( 1 )
+            )
+            .arg(
+                Arg::with_name( "bins" )
+                    .long( "bins" )
+                    .help( "Build all binaries" )
             )
             .arg(
                 Arg::with_name( "example" )
@@ -190,6 +346,11 @@ fn main() {
                     .value_name( "NAME" )
                     .takes_value( true )
             )
+            .arg(
+                Arg::with_name( "examples" )
+                    .long( "examples" )
+                    .help( "Build all examples" )
+            )
             .arg(
                 Arg::with_name( "test" )
                     .long( "test" )
@@ -204,6 +365,132 @@ fn main() {
                     .value_name( "NAME" )
                     .takes_value( true )
             )
+            .arg(
+                Arg::with_name( "message-format" )
+                    .long( "message-format" )
+                    .help( "Selects the stdout output format" )
+                    .value_name( "FMT" )
+                    .takes_value( true )
+                    .default_value( "human" )
+                    .possible_values( &[
+                        "human",
+                        "json"
+                    ])
+            )
+            .arg(
+                Arg::with_name( "emit" )
+                    .long( "emit" )
+                    .help( "Comma-separated list of the kinds of artifacts to produce (default: all of them); `ts` and `html` imply `--typescript` and `--generate-html`" )
+                    .value_name( "KINDS" )
+                    .takes_value( true )
+                    .use_delimiter( true )
+                    .possible_values( &[
+                        "js",
+                        "wasm",
+                        "ts",
+                        "html",
+                        "sig"
+                    ])
+            )
+            .arg(
+                Arg::with_name( "relative-paths" )
+                    .long( "relative-paths" )
+                    .help( "Makes the paths in the JSON messages relative to the workspace root" )
+            )
+            .arg(
+                Arg::with_name( "analyze-deps" )
+                    .long( "analyze-deps" )
+                    .help( "Prints how much of the code in the generated `.wasm` files comes from each crate" )
+            )
+            .arg(
+                Arg::with_name( "print-wasm-size" )
+                    .long( "print-wasm-size" )
+                    .help( "Prints the size of each generated `.wasm` file broken down by its code, data and custom sections" )
+            )
+            .arg(
+                Arg::with_name( "print-fingerprint" )
+                    .long( "print-fingerprint" )
+                    .help( "Prints a hash of everything besides the source code which affects the build of each target instead of building it" )
+            )
+            .arg(
+                Arg::with_name( "runtime" )
+                    .long( "runtime" )
+                    .help( "Selects the kind of the generated `.js` loader (native wasm target only)" )
+                    .value_name( "KIND" )
+                    .takes_value( true )
+                    .possible_values( &[
+                        "standalone",
+                        "library-es6",
+                        "web-extension"
+                    ])
+            )
+            .arg(
+                Arg::with_name( "module-name" )
+                    .long( "module-name" )
+                    .help( "Sets the name the generated `.js` exports the module under (native wasm target only)" )
+                    .value_name( "NAME" )
+                    .takes_value( true )
+            )
+            .arg(
+                Arg::with_name( "generate-html" )
+                    .long( "generate-html" )
+                    .help( "Also generates an `index.html` which loads the `.js` file (native wasm target only)" )
+            )
+            .arg(
+                Arg::with_name( "force" )
+                    .long( "force" )
+                    .help( "Overwrite an already existing `index.html` when used with `--generate-html`" )
+                    .requires( "generate-html" )
+            )
+            .arg(
+                Arg::with_name( "artifact-json" )
+                    .long( "artifact-json" )
+                    .help( "Writes a JSON file listing every artifact which was built" )
+                    .value_name( "PATH" )
+                    .takes_value( true )
+            )
+            .arg(
+                Arg::with_name( "out-dir" )
+                    .long( "out-dir" )
+                    .help( "Copies the final artifacts into this directory, creating it if necessary" )
+                    .value_name( "PATH" )
+                    .takes_value( true )
+            );
+
+    let mut check_subcommand =
+        SubCommand::with_name( "check" )
+            .about( "Type-checks a local package and all of its dependencies without generating any code" )
+            .arg(
+                Arg::with_name( "lib" )
+                    .long( "lib" )
+                    .help( "Check only this package's library" )
+            )
+            .arg(
+                Arg::with_name( "bin" )
+                    .long( "bin" )
+                    .help( "Check only the specified binary; can be given multiple times" )
+                    .value_name( "NAME" )
+                    .takes_value( true )
+                    .multiple( true )
+                    .number_of_values( 1 )
+            )
+            .arg(
+                Arg::with_name( "bins" )
+                    .long( "bins" )
+                    .help( "Check all binaries" )
+            )
+            .arg(
+                Arg::with_name( "example" )
+                    .long( "example" )
+                    .help( "Check only the specified example" )
+                    .value_name( "NAME" )
+                    .takes_value( true )
+            )
+            .arg(
+                Arg::with_name( "examples" )
+                    .long( "examples" )
+                    .help( "Check all examples" )
+            )
             .arg(
                 Arg::with_name( "message-format" )
                     .long( "message-format" )
@@ -225,11 +512,101 @@ fn main() {
                     .long( "no-run" )
                     .help( "Compile, but don't run tests" )
             )
+            .arg(
+                Arg::with_name( "bin" )
+                    .long( "bin" )
+                    .help( "Test only the specified binary; can be given multiple times" )
+                    .value_name( "NAME" )
+                    .takes_value( true )
+                    .multiple( true )
+                    .number_of_values( 1 )
+            )
+            .arg(
+                Arg::with_name( "example" )
+                    .long( "example" )
+                    .help( "Build and run the specified example; it fails the tests if it exits with a non-zero status" )
+                    .value_name( "NAME" )
+                    .takes_value( true )
+            )
+            .arg(
+                Arg::with_name( "examples" )
+                    .long( "examples" )
+                    .help( "Build and run every example the same way `--example` does" )
+            )
             .arg(
                 Arg::with_name( "nodejs" )
                     .long( "nodejs" )
                     .help( "Uses Node.js to run the tests" )
             )
+            .arg(
+                Arg::with_name( "chromium" )
+                    .long( "chromium" )
+                    .help( "Uses this Chromium or Chrome executable instead of looking for one in PATH; can also be set with the CHROMIUM environment variable" )
+                    .value_name( "PATH" )
+                    .takes_value( true )
+            )
+            .arg(
+                Arg::with_name( "chromium-launch-retries" )
+                    .long( "chromium-launch-retries" )
+                    .help( "How many more times to try launching Chromium if it fails to start; overrides `chromium-launch-retries` from the `[test]` section of `Web.toml`" )
+                    .value_name( "N" )
+                    .takes_value( true )
+                    .validator( |value| value.parse::< u32 >().map( |_| () ).map_err( |_| "expected a number of retries".to_owned() ) )
+            )
+            .arg(
+                Arg::with_name( "firefox" )
+                    .long( "firefox" )
+                    .help( "Uses a headless Firefox driven through geckodriver to run the tests" )
+                    .conflicts_with( "nodejs" )
+            )
+            .arg(
+                Arg::with_name( "message-format" )
+                    .long( "message-format" )
+                    .help( "Selects the format of cargo-web's own messages; the output of the tests themselves is passed through as-is" )
+                    .value_name( "FMT" )
+                    .takes_value( true )
+                    .default_value( "human" )
+                    .possible_values( &[
+                        "human",
+                        "json"
+                    ])
+            )
+            .arg(
+                Arg::with_name( "no-force-release" )
+                    .long( "no-force-release" )
+                    .help( "Don't force a release build when testing on the native wasm target" )
+            )
+            .arg(
+                Arg::with_name( "test-env" )
+                    .long( "test-env" )
+                    .help( "Sets an environment variable for the tests; in a browser only the Emscripten targets can read it; can be given multiple times" )
+                    .value_name( "KEY=VALUE" )
+                    .takes_value( true )
+                    .multiple( true )
+                    .number_of_values( 1 )
+            )
+            .arg(
+                Arg::with_name( "test-filter-file" )
+                    .long( "test-filter-file" )
+                    .help( "Reads test name patterns from a file, one per line; patterns prefixed with `!` are skipped" )
+                    .value_name( "PATH" )
+                    .takes_value( true )
+            )
+            .arg(
+                Arg::with_name( "test-shard" )
+                    .long( "test-shard" )
+                    .help( "Runs only the I-th (counting from 1) of N deterministic partitions of the tests; requires `--nodejs`" )
+                    .value_name( "I/N" )
+                    .takes_value( true )
+            )
+            .arg(
+                Arg::with_name( "test-timeout" )
+                    .long( "test-timeout" )
+                    .help( "Kills a test binary which runs for longer than this; overrides `timeout` from the `[test]` section of `Web.toml`" )
+                    .value_name( "SECONDS" )
+                    .takes_value( true )
+                    .validator( |value| value.parse::< u64 >().map( |_| () ).map_err( |_| "expected a number of seconds".to_owned() ) )
+            )
             .arg(
                 Arg::with_name( "passthrough" )
                     .help( "-- followed by anything will pass the arguments to the test runner")
@@ -238,6 +615,42 @@ fn main() {
                     .last( true )
             );
 
+    let mut bench_subcommand =
+        SubCommand::with_name( "bench" )
+            .about( "Compiles and runs benchmarks" )
+            .arg(
+                Arg::with_name( "no-run" )
+                    .long( "no-run" )
+                    .help( "Compile, but don't run benchmarks" )
+            )
+            .arg(
+                Arg::with_name( "nodejs" )
+                    .long( "nodejs" )
+                    .help( "Uses Node.js to run the benchmarks" )
+            )
+            .arg(
+                Arg::with_name( "chromium" )
+                    .long( "chromium" )
+                    .help( "Uses this Chromium or Chrome executable instead of looking for one in PATH; can also be set with the CHROMIUM environment variable" )
+                    .value_name( "PATH" )
+                    .takes_value( true )
+            )
+            .arg(
+                Arg::with_name( "chromium-launch-retries" )
+                    .long( "chromium-launch-retries" )
+                    .help( "How many more times to try launching Chromium if it fails to start; overrides `chromium-launch-retries` from the `[test]` section of `Web.toml`" )
+                    .value_name( "N" )
+                    .takes_value( true )
+                    .validator( |value| value.parse::< u32 >().map( |_| () ).map_err( |_| "expected a number of retries".to_owned() ) )
+            )
+            .arg(
+                Arg::with_name( "passthrough" )
+                    .help( "-- followed by anything will pass the arguments to the benchmark runner")
+                    .multiple( true )
+                    .takes_value( true )
+                    .last( true )
+            );
+
     let mut start_subcommand =
         SubCommand::with_name( "start" )
             .about( "Runs an embedded web server serving the built project" )
@@ -283,34 +696,180 @@ fn main() {
                     .value_name( "PORT" )
                     .takes_value( true )
             )
+            .arg(
+                Arg::with_name( "open" )
+                    .long( "open" )
+                    .help( "Open the served page in your default browser once it's built" )
+            )
             .arg(
                 Arg::with_name( "auto-reload" )
                     .long( "auto-reload" )
                     .help( "Will try to automatically reload the page on rebuild" )
+            )
+            .arg(
+                Arg::with_name( "no-watch" )
+                    .long( "no-watch" )
+                    .help( "Builds only once and serves the result without watching for changes" )
+                    .conflicts_with( "auto-reload" )
+            )
+            .arg(
+                Arg::with_name( "spa" )
+                    .long( "spa" )
+                    .help( "Serves the index page for every unknown path, for single page applications which do their own routing" )
+            )
+            .arg(
+                Arg::with_name( "serve-sources" )
+                    .long( "serve-sources" )
+                    .help( "Serves the crate's `.rs` files so that the browser can show them when debugging through DWARF" )
+            )
+            .arg(
+                Arg::with_name( "basic-auth" )
+                    .long( "basic-auth" )
+                    .help( "Requires every request to authenticate with the given credentials through HTTP Basic authentication" )
+                    .value_name( "USER:PASSWORD" )
+                    .takes_value( true )
+                    .validator( |value| if value.contains( ':' ) { Ok(()) } else { Err( "expected `USER:PASSWORD`".to_owned() ) } )
+            );
+
+    let mut repl_subcommand =
+        SubCommand::with_name( "repl" )
+            .about( "Builds the library for the native wasm target and loads it into a Node.js REPL" );
+
+    let mut deploy_subcommand =
+        SubCommand::with_name( "deploy" )
+            .about( "Builds the project in release mode and writes a ready-to-serve directory" )
+            .arg(
+                Arg::with_name( "bin" )
+                    .long( "bin" )
+                    .help( "Deploy the specified binary" )
+                    .value_name( "NAME" )
+                    .takes_value( true )
+            )
+            .arg(
+                Arg::with_name( "output" )
+                    .long( "output" )
+                    .short( "o" )
+                    .help( "The directory to write everything to; by default `target/deploy`" )
+                    .value_name( "DIR" )
+                    .takes_value( true )
+            );
+
+    let verify_subcommand =
+        SubCommand::with_name( "verify" )
+            .about( "Checks that a deployed directory contains all of the files its `index.html` references" )
+            .arg(
+                Arg::with_name( "directory" )
+                    .help( "The directory to verify" )
+                    .value_name( "DIR" )
+                    .required( true )
+            );
+
+    let config_subcommand =
+        SubCommand::with_name( "config" )
+            .about( "Shows information about the `Web.toml` configuration file" )
+            .arg(
+                Arg::with_name( "schema" )
+                    .long( "schema" )
+                    .help( "Prints a JSON Schema describing `Web.toml`" )
+                    .required( true )
+            );
+
+    let emscripten_subcommand =
+        SubCommand::with_name( "emscripten" )
+            .about( "Manages the prebuilt Emscripten SDK which cargo-web downloads" )
+            .setting( AppSettings::SubcommandRequiredElseHelp )
+            .subcommand(
+                SubCommand::with_name( "update" )
+                    .about( "Downloads and installs a given version of the Emscripten SDK (by default the latest known-good one)" )
+                    .arg(
+                        Arg::with_name( "version" )
+                            .help( "The version to install" )
+                            .value_name( "VERSION" )
+                    )
+                    .arg(
+                        Arg::with_name( "set-default" )
+                            .long( "set-default" )
+                            .help( "Makes the installed version the one which is used when building" )
+                    )
+                    .arg(
+                        Arg::with_name( "remove-previous" )
+                            .long( "remove-previous" )
+                            .help( "Removes every other installed version" )
+                    )
+            )
+            .subcommand(
+                SubCommand::with_name( "clear-cache" )
+                    .about( "Removes the prebuilt Emscripten's cache of system libraries so that they get rebuilt" )
+                    .arg(
+                        Arg::with_name( "global" )
+                            .long( "global" )
+                            .help( "Also removes the cache of the system's Emscripten (`EM_CACHE`, or `~/.emscripten_cache`)" )
+                    )
+            );
+
+    let prepare_emscripten_subcommand =
+        SubCommand::with_name( "prepare-emscripten" )
+            .about( "Downloads the prebuilt Emscripten SDK into cargo-web's cache without building anything" )
+            .arg(
+                Arg::with_name( "version" )
+                    .help( "The version to download; by default the one pinned in `Web.toml` or the default one" )
+                    .value_name( "VERSION" )
             );
 
     build_subcommand = add_shared_build_params( build_subcommand );
+    check_subcommand = add_shared_build_params( check_subcommand );
     test_subcommand = add_shared_build_params( test_subcommand );
+    bench_subcommand = add_shared_build_params( bench_subcommand );
     start_subcommand = add_shared_build_params( start_subcommand );
+    repl_subcommand = add_shared_build_params( repl_subcommand );
+    deploy_subcommand = add_shared_build_params( deploy_subcommand );
 
     let matches = App::new( "cargo-web" )
         .version( env!( "CARGO_PKG_VERSION" ) )
         .setting( AppSettings::SubcommandRequiredElseHelp )
         .setting( AppSettings::VersionlessSubcommands )
+        .arg(
+            Arg::with_name( "overall-timeout" )
+                .long( "overall-timeout" )
+                .help( "Kills cargo-web along with everything it has spawned if the whole invocation takes longer than this; exits with a status of 124" )
+                .value_name( "SECONDS" )
+                .takes_value( true )
+                .global( true )
+                .validator( |value| value.parse::< u64 >().map( |_| () ).map_err( |_| "expected a number of seconds".to_owned() ) )
+        )
         .subcommand( build_subcommand )
+        .subcommand( check_subcommand )
         .subcommand( test_subcommand )
+        .subcommand( bench_subcommand )
         .subcommand( start_subcommand )
+        .subcommand( repl_subcommand )
+        .subcommand( deploy_subcommand )
+        .subcommand( verify_subcommand )
+        .subcommand( config_subcommand )
+        .subcommand( emscripten_subcommand )
+        .subcommand( prepare_emscripten_subcommand )
         .get_matches_from( args );
 
-    let project = CargoProject::new( None );
-    let result = if let Some( matches ) = matches.subcommand_matches( "build" ) {
-        cmd_build::command_build( matches, &project )
-    } else if let Some( matches ) = matches.subcommand_matches( "test" ) {
-        cmd_test::command_test( matches, &project )
-    } else if let Some( matches ) = matches.subcommand_matches( "start" ) {
-        cmd_start::command_start( matches, &project )
+    let overall_timeout = matches.value_of( "overall-timeout" )
+        .or_else( || matches.subcommand().1.and_then( |matches| matches.value_of( "overall-timeout" ) ) );
+
+    if let Some( seconds ) = overall_timeout {
+        if !watchdog::is_supervised() {
+            watchdog::supervise( Duration::from_secs( seconds.parse().unwrap() ) );
+        }
+    }
+
+    // These don't need a crate, so they can be ran anywhere.
+    let result = if let Some( matches ) = matches.subcommand_matches( "verify" ) {
+        cmd_verify::command_verify( matches )
+    } else if let Some( matches ) = matches.subcommand_matches( "config" ) {
+        cmd_config::command_config( matches )
+    } else if let Some( matches ) = matches.subcommand_matches( "emscripten" ) {
+        cmd_emscripten::command_emscripten( matches )
+    } else if let Some( matches ) = matches.subcommand_matches( "prepare-emscripten" ) {
+        cmd_emscripten::command_prepare_emscripten( matches )
     } else {
-        return;
+        run_crate_command( &matches )
     };
 
     match result {
@@ -321,3 +880,24 @@ fn main() {
         }
     }
 }
+
+fn run_crate_command( matches: &ArgMatches ) -> Result< (), error::Error > {
+    let project = CargoProject::new( None );
+    if let Some( matches ) = matches.subcommand_matches( "build" ) {
+        cmd_build::command_build( matches, &project )
+    } else if let Some( matches ) = matches.subcommand_matches( "check" ) {
+        cmd_check::command_check( matches, &project )
+    } else if let Some( matches ) = matches.subcommand_matches( "test" ) {
+        cmd_test::command_test( matches, &project )
+    } else if let Some( matches ) = matches.subcommand_matches( "bench" ) {
+        cmd_bench::command_bench( matches, &project )
+    } else if let Some( matches ) = matches.subcommand_matches( "start" ) {
+        cmd_start::command_start( matches, &project )
+    } else if let Some( matches ) = matches.subcommand_matches( "repl" ) {
+        cmd_repl::command_repl( matches, &project )
+    } else if let Some( matches ) = matches.subcommand_matches( "deploy" ) {
+        cmd_deploy::command_deploy( matches, &project )
+    } else {
+        Ok(())
+    }
+}
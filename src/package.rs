@@ -1,6 +1,9 @@
-use std::process::exit;
-use std::path::PathBuf;
+use std::process::{self, exit};
+use std::path::{Path, PathBuf};
 use std::io::{self, Read, Write};
+use std::time::{Duration, SystemTime};
+use std::sync::mpsc::{channel, Sender, RecvTimeoutError};
+use std::thread;
 use std::fs;
 use std::env;
 
@@ -15,8 +18,6 @@ use reqwest::{
     Url,
 };
 
-use tempdir::TempDir;
-
 use digest::Digest;
 
 use utils::{
@@ -31,7 +32,7 @@ const APP_INFO: app_dirs::AppInfo = app_dirs::AppInfo {
 };
 
 pub struct PrebuiltPackage {
-    pub url: &'static str,
+    pub url: String,
     pub name: &'static str,
     pub version: &'static str,
     pub arch: &'static str,
@@ -57,37 +58,184 @@ fn create_client() -> ReqResult<Client> {
     builder.build()
 }
 
-pub fn download_package( package: &PrebuiltPackage ) -> PathBuf {
-    let url = Url::parse( package.url ).unwrap();
-    let package_filename = url.path_segments().unwrap().last().unwrap().to_owned();
+// The holder of a lock touches it every `LOCK_REFRESH_INTERVAL` seconds,
+// so one which hasn't been touched for this long is assumed to have been
+// left behind by a process which was killed.
+const LOCK_REFRESH_INTERVAL: u64 = 30;
+const STALE_LOCK_AGE: u64 = 5 * 60;
 
-    let unpack_path = app_dirs::app_dir( app_dirs::AppDataType::UserData, &APP_INFO, package.name )
-        .unwrap()
-        .join( package.arch );
-    let version_path = unpack_path.join( ".version" );
+struct PackageLock {
+    path: PathBuf,
+    refresh: Option< Sender< () > >
+}
 
-    if let Ok( existing_version ) = read( &version_path ) {
-        if existing_version == package.version {
-            return unpack_path;
+impl Drop for PackageLock {
+    fn drop( &mut self ) {
+        // Stop refreshing the lock before it's gone.
+        drop( self.refresh.take() );
+        let _ = fs::remove_file( &self.path );
+    }
+}
+
+fn is_lock_stale( path: &Path ) -> bool {
+    fs::metadata( path )
+        .and_then( |metadata| metadata.modified() )
+        .ok()
+        .and_then( |mtime| SystemTime::now().duration_since( mtime ).ok() )
+        .map( |age| age.as_secs() >= STALE_LOCK_AGE )
+        .unwrap_or( false )
+}
+
+fn touch_lock( path: &Path ) -> io::Result< () > {
+    // This doesn't recreate the lock if it was removed in the meantime.
+    let mut fp = fs::OpenOptions::new().write( true ).open( path )?;
+    fp.write_all( process::id().to_string().as_bytes() )
+}
+
+// Keeps the lock fresh for as long as its holder is alive,
+// however long the download might take.
+fn spawn_lock_refresh( path: PathBuf ) -> Sender< () > {
+    let (tx, rx) = channel();
+    thread::spawn( move || {
+        while let Err( RecvTimeoutError::Timeout ) = rx.recv_timeout( Duration::from_secs( LOCK_REFRESH_INTERVAL ) ) {
+            let _ = touch_lock( &path );
+        }
+    });
+
+    tx
+}
+
+// Makes sure that only one process at a time downloads and
+// unpacks a given package, so that e.g. parallel CI jobs
+// won't clobber each other's work.
+fn lock_package( unpack_path: &Path ) -> PackageLock {
+    // This can't use `with_extension` since the version has dots in it.
+    let path = unpack_path.with_file_name( format!( "{}.lock", unpack_path.file_name().unwrap().to_string_lossy() ) );
+    fs::create_dir_all( path.parent().unwrap() ).unwrap();
+
+    let mut printed_message = false;
+    loop {
+        match fs::OpenOptions::new().write( true ).create_new( true ).open( &path ) {
+            Ok( mut fp ) => {
+                let _ = fp.write_all( process::id().to_string().as_bytes() );
+                let refresh = spawn_lock_refresh( path.clone() );
+                return PackageLock { path, refresh: Some( refresh ) };
+            },
+            Err( ref error ) if error.kind() == io::ErrorKind::AlreadyExists => {
+                if is_lock_stale( &path ) {
+                    println_err!( "warning: removing a stale lock file: {:?}", path );
+                    let _ = fs::remove_file( &path );
+                    continue;
+                }
+
+                if !printed_message {
+                    println_err!( "Waiting for another cargo-web process to finish installing into {:?}...", unpack_path );
+                    printed_message = true;
+                }
+
+                thread::sleep( Duration::from_secs( 1 ) );
+            },
+            Err( error ) => panic!( "cannot create the lock file {:?}: {}", path, error )
         }
     }
+}
 
-    if fs::metadata( &unpack_path ).is_ok() {
-        fs::remove_dir_all( &unpack_path ).unwrap();
+/// The directory into which all of the versions of the given package are installed.
+pub fn package_root( name: &str ) -> PathBuf {
+    app_dirs::app_dir( app_dirs::AppDataType::UserData, &APP_INFO, name ).unwrap()
+}
+
+// Older versions of cargo-web kept a single version per architecture.
+fn legacy_unpack_path( package: &PrebuiltPackage ) -> PathBuf {
+    package_root( package.name ).join( package.arch )
+}
+
+// Every version gets its own directory so that
+// multiple versions can be installed at once.
+fn unpack_path( package: &PrebuiltPackage ) -> PathBuf {
+    package_root( package.name ).join( format!( "{}-{}", package.version, package.arch ) )
+}
+
+/// Removes every other installed version of the package
+/// which was built for the same architecture.
+pub fn remove_other_versions( package: &PrebuiltPackage ) {
+    let current = unpack_path( package );
+    let suffix = format!( "-{}", package.arch );
+    let entries = match fs::read_dir( package_root( package.name ) ) {
+        Ok( entries ) => entries,
+        Err( _ ) => return
+    };
+
+    for entry in entries {
+        let path = entry.unwrap().path();
+        let is_other_version = path != current && path.is_dir() && path.file_name().and_then( |name| name.to_str() ).map( |name| {
+            name == package.arch || name.ends_with( &suffix )
+        }).unwrap_or( false );
+
+        if is_other_version {
+            println_err!( "Removing {:?}...", path );
+            fs::remove_dir_all( &path ).unwrap();
+        }
     }
+}
 
-    fs::create_dir_all( &unpack_path ).unwrap();
+// The downloaded archives are kept around so that reinstalling a package
+// (e.g. after `--remove-previous` or on a CI machine with a warm cache)
+// doesn't have to download it again.
+fn archive_cache_root() -> PathBuf {
+    app_dirs::app_dir( app_dirs::AppDataType::UserCache, &APP_INFO, "downloads" ).unwrap()
+}
+
+// Written next to an archive only after its hash was verified,
+// so an interrupted download is never mistaken for a complete one.
+fn checksum_path( archive_path: &Path ) -> PathBuf {
+    archive_path.with_file_name( format!( "{}.sha256", archive_path.file_name().unwrap().to_string_lossy() ) )
+}
+
+fn hash_to_string( hasher: sha2::Sha256 ) -> String {
+    hasher.result().map( |byte| format!( "{:02x}", byte ) ).join( "" )
+}
+
+/// The SHA-256 of the file's contents, in hex.
+pub fn hash_file( path: &Path ) -> io::Result< String > {
+    let mut fp = fs::File::open( path )?;
+    let mut buffer = Vec::new();
+    buffer.resize( 1024 * 1024, 0 );
 
-    println_err!( "Downloading {}...", package_filename );
+    let mut hasher = sha2::Sha256::default();
+    loop {
+        let length = match fp.read( &mut buffer ) {
+            Ok( 0 ) => break,
+            Ok( length ) => length,
+            Err( ref err ) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err( err ) => return Err( err )
+        };
+
+        hasher.input( &buffer[ 0..length ] );
+    }
+
+    Ok( hash_to_string( hasher ) )
+}
+
+fn is_cached_archive_valid( package: &PrebuiltPackage, archive_path: &Path ) -> bool {
+    let recorded_hash = match read( checksum_path( archive_path ) ) {
+        Ok( hash ) => hash,
+        Err( _ ) => return false
+    };
+
+    recorded_hash.trim() == package.hash &&
+        hash_file( archive_path ).map( |hash| hash == package.hash ).unwrap_or( false )
+}
+
+// Downloads the archive into `archive_path`, returning its hash.
+fn fetch_archive( package: &PrebuiltPackage, url: Url, archive_path: &Path ) -> String {
     let client = create_client().unwrap();
     let mut response = client.get( url )
         .header( header::Connection::close() )
         .send()
         .unwrap();
 
-    let tmpdir = TempDir::new( format!( "cargo-web-{}-download", package.name ).as_str() ).unwrap();
-    let dlpath = tmpdir.path().join( &package_filename );
-    let mut fp = fs::File::create( &dlpath ).unwrap();
+    let mut fp = fs::File::create( archive_path ).unwrap();
 
     let length: Option< header::ContentLength > = response.headers().get().cloned();
     let length = length.map( |length| length.0 ).unwrap_or( package.size );
@@ -113,20 +261,81 @@ pub fn download_package( package: &PrebuiltPackage ) -> PathBuf {
     }
 
     pb.finish();
+    hash_to_string( hasher )
+}
+
+pub fn download_package( package: &PrebuiltPackage ) -> PathBuf {
+    let url = Url::parse( &package.url ).unwrap();
+    let package_filename = url.path_segments().unwrap().last().unwrap().to_owned();
+
+    let unpack_path = unpack_path( package );
+    let version_path = unpack_path.join( ".version" );
 
-    let actual_hash = hasher.result();
-    let actual_hash = actual_hash.map( |byte| format!( "{:02x}", byte ) ).join( "" );
+    if let Ok( existing_version ) = read( &version_path ) {
+        if existing_version == package.version {
+            return unpack_path;
+        }
+    }
+
+    let lock = lock_package( &unpack_path );
+
+    // Someone else might have installed it while we were waiting.
+    if let Ok( existing_version ) = read( &version_path ) {
+        if existing_version == package.version {
+            return unpack_path;
+        }
+    }
+
+    // There's no need to download it again if the same version
+    // was already installed in the old location.
+    let legacy_path = legacy_unpack_path( package );
+    if read( legacy_path.join( ".version" ) ).ok().map( |version| version == package.version ).unwrap_or( false ) {
+        if fs::metadata( &unpack_path ).is_ok() {
+            fs::remove_dir_all( &unpack_path ).unwrap();
+        }
+
+        fs::rename( &legacy_path, &unpack_path ).unwrap();
+        return unpack_path;
+    }
+
+    if fs::metadata( &unpack_path ).is_ok() {
+        fs::remove_dir_all( &unpack_path ).unwrap();
+    }
+
+    fs::create_dir_all( &unpack_path ).unwrap();
+
+    let archive_path = archive_cache_root().join( &package_filename );
+    if is_cached_archive_valid( package, &archive_path ) {
+        println_err!( "Using the already downloaded {}...", package_filename );
+    } else {
+        if archive_path.exists() {
+            println_err!( "warning: the previously downloaded {} is incomplete or corrupted; downloading it again", package_filename );
+        }
+
+        let _ = fs::remove_file( &archive_path );
+        let _ = fs::remove_file( checksum_path( &archive_path ) );
+
+        println_err!( "Downloading {}...", package_filename );
+        let partial_path = archive_path.with_file_name( format!( "{}.part", package_filename ) );
+        let actual_hash = fetch_archive( package, url, &partial_path );
+
+        if actual_hash != package.hash {
+            let _ = fs::remove_file( &partial_path );
+            println_err!( "error: the hash of {} doesn't match the expected hash!", package_filename );
+            println_err_cont!( "  actual: {}", actual_hash );
+            println_err_cont!( "  expected: {}", package.hash );
+            drop( lock );
+            exit( 101 );
+        }
 
-    if actual_hash != package.hash {
-        println_err!( "error: the hash of {} doesn't match the expected hash!", package_filename );
-        println_err!( "  actual: {}", actual_hash );
-        println_err!( "  expected: {}", package.hash );
-        exit( 101 );
+        fs::rename( &partial_path, &archive_path ).unwrap();
+        write( checksum_path( &archive_path ), package.hash ).unwrap();
     }
 
     println_err!( "Unpacking {}...", package_filename );
-    unpack( &dlpath, &unpack_path ).unwrap();
+    unpack( &archive_path, &unpack_path ).unwrap();
     write( &version_path, package.version ).unwrap();
+    drop( lock );
 
     println_err!( "Package {} was successfully installed!", package_filename );
     return unpack_path;
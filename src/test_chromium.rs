@@ -1,12 +1,15 @@
 use std::collections::BTreeMap;
-use std::process::{Command, Stdio};
-use std::sync::mpsc::channel;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{channel, RecvTimeoutError};
 use std::sync::{Mutex, Arc};
 use std::time::Duration;
 use std::thread;
 use std::time::Instant;
 use std::io::{BufRead, BufReader};
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::env;
+use std::net::SocketAddr;
 
 use rouille;
 use tempdir::TempDir;
@@ -17,6 +20,7 @@ use regex::Regex;
 use cargo_shim::CargoResult;
 
 use build::BuildArgsMatcher;
+use cmd_test::{find_artifact, record_failure, test_env};
 use error::Error;
 use utils::{
     read,
@@ -25,6 +29,12 @@ use utils::{
 };
 use chrome_devtools::{Connection, Reply, ReplyError, ConsoleApiCalledBody, ExceptionThrownBody};
 
+/// How long the tests may run in a browser if no timeout was configured.
+pub const DEFAULT_BROWSER_TIMEOUT: u64 = 60;
+
+/// How many more times Chromium is launched after it fails to start, if not configured.
+pub const DEFAULT_CHROMIUM_LAUNCH_RETRIES: u32 = 3;
+
 const DEFAULT_TEST_INDEX_HTML: &'static str = r#"
 <!DOCTYPE html>
 <head>
@@ -36,6 +46,33 @@ const DEFAULT_TEST_INDEX_HTML: &'static str = r#"
         var Module = {};
         __cargo_web.status = new Promise( function( resolve ) { Module['onExit'] = resolve; } );
         Module['arguments'] = [{{#each arguments}} "{{{ this }}}", {{/each}}];
+        {{#if environment}}
+        // Emscripten's `ENV` is what `std::env` reads.
+        Module['preRun'] = [ function() {
+            var environment = {{{ environment }}};
+            Object.keys( environment ).forEach( function( key ) { ENV[ key ] = environment[ key ]; } );
+        }];
+        {{/if}}
+        {{#if capture_console}}
+        // For browsers whose console can't be read from the outside.
+        __cargo_web.output = [];
+        __cargo_web.exit_status = null;
+        __cargo_web.exception = null;
+        [ "log", "debug", "info", "warn", "error" ].forEach( function( kind ) {
+            var original = console[ kind ];
+            console[ kind ] = function() {
+                __cargo_web.output.push( Array.prototype.map.call( arguments, String ).join( " " ) );
+                original.apply( console, arguments );
+            };
+        });
+        window.addEventListener( "error", function( event ) { __cargo_web.exception = String( event.message ); } );
+        __cargo_web.status.then( function( status ) { __cargo_web.exit_status = status; } );
+        __cargo_web.poll = function() {
+            var output = __cargo_web.output;
+            __cargo_web.output = [];
+            return { output: output, status: __cargo_web.exit_status, exception: __cargo_web.exception };
+        };
+        {{/if}}
     </script>
 </head>
 <body>
@@ -44,30 +81,133 @@ const DEFAULT_TEST_INDEX_HTML: &'static str = r#"
 </html>
 "#;
 
-pub fn test_in_chromium(
-    build_matcher: &BuildArgsMatcher,
-    build: CargoResult,
-    arg_passthrough: &Vec< &OsStr >,
-    any_failure: &mut bool
-) -> Result< (), Error > {
-    let chromium_executable = if cfg!( windows ) && check_if_command_exists( "chrome.exe", None ) {
-        "chrome.exe"
-    } else if check_if_command_exists( "chromium", None ) {
-        "chromium"
-    } else if check_if_command_exists( "google-chrome", None ) {
-        "google-chrome"
-    } else if check_if_command_exists( "google-chrome-stable", None ) {
-        "google-chrome-stable"
-    } else {
-        return Err( Error::EnvironmentError( "you need to have either Chromium or Chrome installed and in your PATH to run the tests!".into() ) );
+struct Chromium {
+    child: Child,
+    connection: Connection,
+    // Has to be kept alive until chromium is killed.
+    _profile: TempDir
+}
+
+struct LaunchFailure {
+    error: Error,
+    command: String,
+    stderr: Vec< String >
+}
+
+fn kill( child: &mut Child ) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+fn launch_chromium( executable: &OsStr ) -> Result< Chromium, LaunchFailure > {
+    let profile = TempDir::new( "cargo-web-chromium-profile" ).unwrap();
+    let mut command = Command::new( executable );
+    command
+        .arg( "--disable-gpu" )
+        .arg( "--no-first-run" )
+        .arg( "--disable-restore-session-state" )
+        .arg( "--no-default-browser-check" )
+        .arg( "--disable-java" )
+        .arg( "--disable-client-side-phishing-detection" )
+        .arg( "--headless" )
+        .arg( "--remote-debugging-port=0" )
+        .arg( format!( "--user-data-dir={}", profile.path().to_string_lossy() ) )
+        .arg( "about:blank" );
+
+    command
+        .stdout( Stdio::null() )
+        .stderr( Stdio::piped() )
+        .stdin( Stdio::null() );
+
+    let command_line = format!( "{:?}", command );
+    let failure = |error: Error, stderr: Vec< String >| LaunchFailure {
+        error,
+        command: command_line.clone(),
+        stderr
     };
 
+    debug!( "Launching chromium..." );
+    let mut child = command.spawn()
+        .map_err( |err| failure( Error::RuntimeError( "cannot launch chromium".into(), err.into() ), Vec::new() ) )?;
+
+    let stderr = BufReader::new( child.stderr.take().unwrap() );
+    let stderr_lines = Arc::new( Mutex::new( Vec::new() ) );
+    let reader_stderr_lines = stderr_lines.clone();
+    let devtools_regex = Regex::new( r"DevTools listening on (ws://[^:]+:\d+)" ).unwrap();
+    let (url_tx, url_rx) = channel();
+    let reader = thread::spawn( move || {
+        for line in stderr.lines() {
+            let line = match line {
+                Ok( line ) => line,
+                Err( _ ) => break
+            };
+
+            if let Some( captures ) = devtools_regex.captures( &line ) {
+                let url = captures.get( 1 ).unwrap().as_str().to_owned();
+                let _ = url_tx.send( url );
+                break;
+            }
+
+            reader_stderr_lines.lock().unwrap().push( line );
+        }
+    });
+
+    let url = match url_rx.recv_timeout( Duration::from_secs( 10 ) ) {
+        Ok( url ) => url,
+        Err( err ) => {
+            kill( &mut child );
+            let _ = reader.join();
+            let message = match err {
+                RecvTimeoutError::Timeout => "timeout while waiting for chromium to start",
+                RecvTimeoutError::Disconnected => "chromium exited before it started listening for devtools connections"
+            };
+
+            let stderr = stderr_lines.lock().unwrap().clone();
+            return Err( failure( Error::RuntimeError( message.into(), err.into() ), stderr ) );
+        }
+    };
+
+    debug!( "Chromium in listening on: {}", url );
+    let connection = match Connection::connect( &format!( "{}/json", url ) ) {
+        Ok( connection ) => connection,
+        Err( err ) => {
+            kill( &mut child );
+            let stderr = stderr_lines.lock().unwrap().clone();
+            return Err( failure( Error::RuntimeError( format!( "devtools connection to chromium at {} failed", url ), err.into() ), stderr ) );
+        }
+    };
+
+    Ok( Chromium {
+        child,
+        connection,
+        _profile: profile
+    })
+}
+
+/// Serves the test page along with the build's artifacts on a random port.
+pub fn spawn_test_server(
+    build_matcher: &BuildArgsMatcher,
+    build: &CargoResult,
+    arg_passthrough: &Vec< &OsStr >,
+    capture_console: bool
+) -> Result< SocketAddr, Error > {
     let app_js = Arc::new( Mutex::new( String::new() ) );
     let server_app_js = app_js.clone();
     let handlebars = Handlebars::new();
     let mut template_data = BTreeMap::new();
     let arg_passthrough: Vec<_> = arg_passthrough.iter().map( |arg| arg.to_str().unwrap() ).collect();
-    template_data.insert( "arguments", arg_passthrough );
+    template_data.insert( "arguments", json!( arg_passthrough ) );
+    template_data.insert( "capture_console", json!( capture_console ) );
+
+    let environment: serde_json::Map< String, Value > = test_env( build_matcher )?.into_iter()
+        .map( |(key, value)| (key, Value::String( value )) )
+        .collect();
+    if !environment.is_empty() {
+        // So that a `</script>` in one of the values can't end the script early.
+        let environment = Value::Object( environment ).to_string().replace( "</", "<\\/" );
+        template_data.insert( "environment", json!( environment ) );
+    }
+
     let test_index = handlebars.template_render( DEFAULT_TEST_INDEX_HTML, &template_data ).unwrap();
     let app_wasm: Arc< Mutex< Option< Vec< u8 > > > > = Arc::new( Mutex::new( None ) );
     let wasm_url = Arc::new( Mutex::new( None ) );
@@ -100,70 +240,107 @@ pub fn test_in_chromium(
         server.run();
     });
 
-    let artifact = build.artifacts().iter()
-        .find( |artifact| artifact.extension().map( |ext| ext == "js" ).unwrap_or( false ) )
-        .expect( "internal error: no .js file found" );
-
+    let artifact = find_artifact( build, "js" )?;
     if build_matcher.targeting_wasm() {
-        let wasm_artifact = build.artifacts().iter()
-            .find( |artifact| artifact.extension().map( |ext| ext == "wasm" ).unwrap_or( false ) )
-            .expect( "internal error: no .wasm file found" );
+        let wasm_artifact = find_artifact( build, "wasm" )?;
 
         *wasm_url.lock().unwrap() = Some( format!( "/{}", wasm_artifact.file_name().unwrap().to_str().unwrap() ) );
         *app_wasm.lock().unwrap() = Some( read_bytes( wasm_artifact ).unwrap() );
     }
 
     *app_js.lock().unwrap() = read( artifact ).unwrap();
+    Ok( server_address )
+}
 
-    let tmpdir = TempDir::new( "cargo-web-chromium-profile" ).unwrap();
-    let tmpdir = tmpdir.path().to_string_lossy();
-    let mut command = Command::new( chromium_executable );
-    command
-        .arg( "--disable-gpu" )
-        .arg( "--no-first-run" )
-        .arg( "--disable-restore-session-state" )
-        .arg( "--no-default-browser-check" )
-        .arg( "--disable-java" )
-        .arg( "--disable-client-side-phishing-detection" )
-        .arg( "--headless" )
-        .arg( "--remote-debugging-port=0" )
-        .arg( format!( "--user-data-dir={}", tmpdir ) )
-        .arg( "about:blank" );
+fn find_chromium() -> Result< &'static str, Error > {
+    if cfg!( windows ) && check_if_command_exists( "chrome.exe", None ) {
+        Ok( "chrome.exe" )
+    } else if check_if_command_exists( "chromium", None ) {
+        Ok( "chromium" )
+    } else if check_if_command_exists( "google-chrome", None ) {
+        Ok( "google-chrome" )
+    } else if check_if_command_exists( "google-chrome-stable", None ) {
+        Ok( "google-chrome-stable" )
+    } else {
+        Err( Error::EnvironmentError( "you need to have either Chromium or Chrome installed and in your PATH to run the tests!".into() ) )
+    }
+}
 
-    command
-        .stdout( Stdio::null() )
-        .stderr( Stdio::piped() )
-        .stdin( Stdio::null() );
+// Either `--chromium` or the `CHROMIUM` environment variable.
+fn chromium_override( build_matcher: &BuildArgsMatcher ) -> Option< OsString > {
+    build_matcher.matches.value_of_os( "chromium" )
+        .map( |path| path.to_owned() )
+        .or_else( || env::var_os( "CHROMIUM" ) )
+}
 
-    debug!( "Launching chromium..." );
-    let mut child = command.spawn()
-        .map_err( |err| Error::RuntimeError( "cannot launch chromium".into(), err.into() ) )?;
+fn check_executable( path: &OsStr ) -> Result< (), Error > {
+    match fs::metadata( path ) {
+        Ok( ref metadata ) if metadata.is_file() && is_executable( metadata ) => Ok(()),
+        Ok( ref metadata ) if metadata.is_file() => Err( Error::EnvironmentError( format!( "the Chromium executable {:?} isn't executable", path ) ) ),
+        _ => Err( Error::EnvironmentError( format!( "the Chromium executable {:?} doesn't exist", path ) ) )
+    }
+}
 
-    let stderr = BufReader::new( child.stderr.take().unwrap() );
-    let devtools_regex = Regex::new( r"DevTools listening on (ws://[^:]+:\d+)" ).unwrap();
-    let (url_tx, url_rx) = channel();
-    thread::spawn( move || {
-        for line in stderr.lines() {
-            let line = match line {
-                Ok( line ) => line,
-                Err( _ ) => break
-            };
+#[cfg(unix)]
+fn is_executable( metadata: &fs::Metadata ) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
 
-            if let Some( captures ) = devtools_regex.captures( &line ) {
-                let url = captures.get( 1 ).unwrap().as_str().to_owned();
-                let _ = url_tx.send( url );
-                break;
-            }
-        }
-    });
+#[cfg(not(unix))]
+fn is_executable( _: &fs::Metadata ) -> bool {
+    true
+}
 
-    let url = url_rx.recv_timeout( Duration::from_secs( 10 ) )
-        .map_err( |err| Error::RuntimeError( "timeout while waiting for chromium to start".into(), err.into() ) )?;
+pub fn test_in_chromium(
+    build_matcher: &BuildArgsMatcher,
+    build: CargoResult,
+    arg_passthrough: &Vec< &OsStr >,
+    launch_retries: u32,
+    timeout: Option< Duration >,
+    failure_code: &mut Option< i32 >
+) -> Result< (), Error > {
+    let chromium_executable = match chromium_override( build_matcher ) {
+        Some( path ) => {
+            check_executable( &path )?;
+            path
+        },
+        None => find_chromium()?.into()
+    };
 
-    debug!( "Chromium in listening on: {}", url );
-    let mut connection = Connection::connect( &format!( "{}/json", url ) )
-        .map_err( |err| Error::RuntimeError( "devtools connection to chromium failed".into(), err.into() ) )?;
+    let server_address = spawn_test_server( build_matcher, &build, arg_passthrough, false )?;
+
+    let mut attempt = 0;
+    let chromium = loop {
+        match launch_chromium( &chromium_executable ) {
+            Ok( chromium ) => break chromium,
+            Err( failure ) => {
+                if attempt < launch_retries {
+                    let delay = Duration::from_secs( 1 << attempt.min( 3 ) );
+                    println_err!( "warning: {}; retrying in {}s...", failure.error, delay.as_secs() );
+                    thread::sleep( delay );
+                    attempt += 1;
+                    continue;
+                }
+
+                println_err!( "error: cannot launch chromium" );
+                println_err_cont!( "  command: {}", failure.command );
+                if failure.stderr.is_empty() {
+                    println_err_cont!( "  (nothing was printed to stderr)" );
+                } else {
+                    println_err_cont!( "  stderr:" );
+                    for line in &failure.stderr {
+                        println_err_cont!( "    {}", line );
+                    }
+                }
+
+                return Err( Error::EnvironmentError( format!( "chromium failed to start after {} attempt(s): {}", attempt + 1, failure.error ) ) );
+            }
+        }
+    };
 
+    #[allow(unused_variables)]
+    let Chromium { mut child, mut connection, _profile: profile } = chromium;
     connection.send_cmd( "Page.enable", Value::Null );
     connection.send_cmd( "Runtime.enable", Value::Null );
     connection.send_cmd(
@@ -176,7 +353,7 @@ pub fn test_in_chromium(
     let mut print_counter = 0;
     let mut finished = false;
     let start = Instant::now();
-    let time_limit = Duration::from_secs( 60 );
+    let time_limit = timeout.unwrap_or( Duration::from_secs( DEFAULT_BROWSER_TIMEOUT ) );
     let mut get_status_req = None;
     loop {
         let elapsed = start.elapsed();
@@ -206,7 +383,7 @@ pub fn test_in_chromium(
                 let status = body.get( "result" ).unwrap().get( "value" ).unwrap().as_u64().unwrap();
                 if status != 0 {
                     println_err!( "error: process exited with a status of {}", status );
-                    *any_failure = true;
+                    record_failure( failure_code, Some( status as i32 ) );
                 }
                 break;
             },
@@ -233,7 +410,7 @@ pub fn test_in_chromium(
                     println_err!( "error: source: {}:{}:{}", url, body.exception_details.line_number, body.exception_details.column_number );
                 }
                 // TODO: Better error message.
-                *any_failure = true;
+                record_failure( failure_code, None );
                 finished = true;
                 break;
             },
@@ -282,8 +459,8 @@ pub fn test_in_chromium(
     }
 
     if !finished {
-        println_err!( "error: tests timed out!" );
-        *any_failure = true;
+        println_err!( "error: test binary timed out after {} seconds", time_limit.as_secs() );
+        record_failure( failure_code, None );
     }
 
     debug!( "Testing finished; waiting for chromium to die..." );
@@ -0,0 +1,235 @@
+use std::process::{Child, Command, Stdio};
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+use std::thread;
+use std::ffi::OsStr;
+use std::mem;
+
+use reqwest;
+use serde_json::Value;
+
+use cargo_shim::CargoResult;
+
+use build::BuildArgsMatcher;
+use cmd_test::record_failure;
+use error::Error;
+use utils::check_if_command_exists;
+use test_chromium::{DEFAULT_BROWSER_TIMEOUT, spawn_test_server};
+
+fn find_firefox() -> Option< &'static str > {
+    if cfg!( windows ) && check_if_command_exists( "firefox.exe", None ) {
+        Some( "firefox.exe" )
+    } else if check_if_command_exists( "firefox", None ) {
+        Some( "firefox" )
+    } else {
+        None
+    }
+}
+
+fn kill( child: &mut Child ) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+// geckodriver has to be told which port to listen on,
+// so we grab a free one from the OS first.
+fn free_port() -> u16 {
+    let listener = TcpListener::bind( "localhost:0" ).unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+fn webdriver_error( message: &str, reply: &Value ) -> Error {
+    let details = reply.get( "value" )
+        .and_then( |value| value.get( "message" ) )
+        .and_then( |message| message.as_str() )
+        .unwrap_or( "unknown error" )
+        .to_owned();
+
+    Error::RuntimeError( message.into(), details.into() )
+}
+
+struct Session {
+    client: reqwest::Client,
+    url: String
+}
+
+impl Session {
+    fn start( client: reqwest::Client, driver_url: &str, firefox_executable: &str ) -> Result< Self, Error > {
+        let capabilities = json!({
+            "capabilities": {
+                "alwaysMatch": {
+                    "moz:firefoxOptions": {
+                        "binary": firefox_executable,
+                        "args": [ "-headless" ]
+                    }
+                }
+            }
+        });
+
+        let reply = post( &client, &format!( "{}/session", driver_url ), &capabilities )?;
+        let id = match reply.get( "value" ).and_then( |value| value.get( "sessionId" ) ).and_then( |id| id.as_str() ) {
+            Some( id ) => id.to_owned(),
+            None => return Err( webdriver_error( "cannot start a Firefox session", &reply ) )
+        };
+
+        Ok( Session {
+            client,
+            url: format!( "{}/session/{}", driver_url, id )
+        })
+    }
+
+    fn navigate( &self, url: &str ) -> Result< (), Error > {
+        let reply = post( &self.client, &format!( "{}/url", self.url ), &json!({ "url": url }) )?;
+        if reply.get( "value" ).map( |value| value.is_null() ).unwrap_or( false ) {
+            Ok(())
+        } else {
+            Err( webdriver_error( "cannot load the test page in Firefox", &reply ) )
+        }
+    }
+
+    fn execute( &self, script: &str ) -> Result< Value, Error > {
+        let mut reply = post( &self.client, &format!( "{}/execute/sync", self.url ), &json!({ "script": script, "args": [] }) )?;
+        if reply.get( "value" ).and_then( |value| value.get( "error" ) ).and_then( |error| error.as_str() ).is_some() {
+            return Err( webdriver_error( "cannot run a script in Firefox", &reply ) );
+        }
+
+        Ok( reply.get_mut( "value" ).map( |value| mem::replace( value, Value::Null ) ).unwrap_or( Value::Null ) )
+    }
+
+    fn close( self ) {
+        let _ = self.client.delete( &self.url ).send();
+    }
+}
+
+fn post( client: &reqwest::Client, url: &str, body: &Value ) -> Result< Value, Error > {
+    let mut response = client.post( url ).json( body ).send()
+        .map_err( |err| Error::RuntimeError( "error while communicating with geckodriver".into(), err.into() ) )?;
+
+    response.json()
+        .map_err( |err| Error::RuntimeError( "geckodriver returned an invalid reply".into(), err.into() ) )
+}
+
+fn launch_geckodriver() -> Result< (Child, String), Error > {
+    let port = free_port();
+    let mut child = Command::new( "geckodriver" )
+        .arg( "--port" )
+        .arg( port.to_string() )
+        .stdout( Stdio::null() )
+        .stderr( Stdio::null() )
+        .stdin( Stdio::null() )
+        .spawn()
+        .map_err( |err| Error::RuntimeError( "cannot launch geckodriver".into(), err.into() ) )?;
+
+    let url = format!( "http://localhost:{}", port );
+    let start = Instant::now();
+    loop {
+        if reqwest::get( &format!( "{}/status", url ) ).is_ok() {
+            return Ok( (child, url) );
+        }
+
+        if start.elapsed() >= Duration::from_secs( 10 ) {
+            kill( &mut child );
+            return Err( Error::RuntimeError( "timeout while waiting for geckodriver to start".into(), format!( "nothing was listening on {}", url ).into() ) );
+        }
+
+        thread::sleep( Duration::from_millis( 100 ) );
+    }
+}
+
+pub fn test_in_firefox(
+    build_matcher: &BuildArgsMatcher,
+    build: CargoResult,
+    arg_passthrough: &Vec< &OsStr >,
+    timeout: Option< Duration >,
+    failure_code: &mut Option< i32 >
+) -> Result< (), Error > {
+    if !check_if_command_exists( "geckodriver", None ) {
+        return Err( Error::EnvironmentError( "geckodriver not found; please install it!".into() ) );
+    }
+
+    let firefox_executable = match find_firefox() {
+        Some( executable ) => executable,
+        None => return Err( Error::EnvironmentError( "Firefox not found; please install it!".into() ) )
+    };
+
+    // Firefox's console isn't exposed over WebDriver, so the page
+    // itself collects the output until we poll for it.
+    let server_address = spawn_test_server( build_matcher, &build, arg_passthrough, true )?;
+    let (mut geckodriver, driver_url) = launch_geckodriver()?;
+
+    let time_limit = timeout.unwrap_or( Duration::from_secs( DEFAULT_BROWSER_TIMEOUT ) );
+    let result = run_session( build_matcher, &driver_url, firefox_executable, server_address.port(), time_limit, failure_code );
+
+    debug!( "Testing finished; waiting for geckodriver to die..." );
+    kill( &mut geckodriver );
+
+    result
+}
+
+fn run_session(
+    build_matcher: &BuildArgsMatcher,
+    driver_url: &str,
+    firefox_executable: &str,
+    port: u16,
+    time_limit: Duration,
+    failure_code: &mut Option< i32 >
+) -> Result< (), Error > {
+    let session = Session::start( reqwest::Client::new(), driver_url, firefox_executable )?;
+    let result = wait_for_exit( build_matcher, &session, port, time_limit, failure_code );
+    session.close();
+    result
+}
+
+fn wait_for_exit(
+    build_matcher: &BuildArgsMatcher,
+    session: &Session,
+    port: u16,
+    time_limit: Duration,
+    failure_code: &mut Option< i32 >
+) -> Result< (), Error > {
+    session.navigate( &format!( "http://localhost:{}", port ) )?;
+
+    let mut print_counter = 0;
+    let start = Instant::now();
+    loop {
+        let reply = session.execute( "return __cargo_web.poll();" )?;
+        if let Some( output ) = reply.get( "output" ).and_then( |output| output.as_array() ) {
+            for line in output {
+                let line = line.as_str().unwrap_or( "" );
+                if build_matcher.targeting_emscripten() {
+                    if print_counter == 0 && line.starts_with( "pre-main" ) {
+                        continue;
+                    } else if print_counter == 1 && line.trim().is_empty() {
+                        continue;
+                    }
+                }
+
+                println!( "{}", line );
+                print_counter += 1;
+            }
+        }
+
+        if let Some( exception ) = reply.get( "exception" ).and_then( |exception| exception.as_str() ) {
+            println_err!( "error: unhandled exception thrown" );
+            println_err!( "error:     {}", exception );
+            record_failure( failure_code, None );
+            return Ok(());
+        }
+
+        if let Some( status ) = reply.get( "status" ).and_then( |status| status.as_u64() ) {
+            if status != 0 {
+                println_err!( "error: process exited with a status of {}", status );
+                record_failure( failure_code, Some( status as i32 ) );
+            }
+            return Ok(());
+        }
+
+        if start.elapsed() >= time_limit {
+            println_err!( "error: test binary timed out after {} seconds", time_limit.as_secs() );
+            record_failure( failure_code, None );
+            return Ok(());
+        }
+
+        thread::sleep( Duration::from_millis( 100 ) );
+    }
+}
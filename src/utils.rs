@@ -2,6 +2,8 @@ use std::process::{Command, Stdio};
 use std::path::Path;
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::fmt;
 use std::env;
 
 use libflate::gzip;
@@ -9,11 +11,69 @@ use tar;
 
 macro_rules! println_err(
     ($($arg:tt)*) => {{
-        use std::io::{Write, stderr};
-        writeln!( &mut stderr(), $($arg)* ).expect( "writeln to stderr failed" );
+        ::utils::print_diagnostic( format_args!( $($arg)* ), false );
     }}
 );
 
+// For the lines which explain the error printed right before them,
+// so that they get through `--quiet` along with it.
+macro_rules! println_err_cont(
+    ($($arg:tt)*) => {{
+        ::utils::print_diagnostic( format_args!( $($arg)* ), true );
+    }}
+);
+
+static JSON_DIAGNOSTICS: AtomicBool = ATOMIC_BOOL_INIT;
+static QUIET: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// Makes `println_err!` print JSON objects to stdout instead, for `--message-format json`.
+pub fn set_json_diagnostics( enabled: bool ) {
+    JSON_DIAGNOSTICS.store( enabled, Ordering::SeqCst );
+}
+
+/// Makes `println_err!` only print errors, for `--quiet`.
+pub fn set_quiet( enabled: bool ) {
+    QUIET.store( enabled, Ordering::SeqCst );
+}
+
+// Our messages are prefixed with their level, just like cargo's.
+fn split_level( message: &str ) -> (&str, &str) {
+    for level in &[ "error", "warning", "note" ] {
+        if message.starts_with( level ) && message[ level.len().. ].starts_with( ": " ) {
+            return (level, message[ level.len() + 2.. ].trim());
+        }
+    }
+
+    ("info", message.trim())
+}
+
+pub fn print_diagnostic( arguments: fmt::Arguments, is_error_continuation: bool ) {
+    let message = fmt::format( arguments );
+    let (level, text) = split_level( &message );
+
+    if QUIET.load( Ordering::SeqCst ) && level != "error" && !is_error_continuation {
+        return;
+    }
+
+    if !JSON_DIAGNOSTICS.load( Ordering::SeqCst ) {
+        writeln!( &mut io::stderr(), "{}", message ).expect( "writeln to stderr failed" );
+        return;
+    }
+
+    // Empty lines are only there to make the human output more readable.
+    if text.is_empty() {
+        return;
+    }
+
+    let value = json!({
+        "reason": "cargo-web-message",
+        "level": level,
+        "message": text
+    });
+
+    writeln!( &mut io::stdout(), "{}", value ).expect( "writeln to stdout failed" );
+}
+
 pub struct ExecutionStatus {
     status: Option< i32 >
 }
@@ -100,3 +160,11 @@ pub fn unpack< I: AsRef< Path >, O: AsRef< Path > >( input_path: I, output_path:
 
     Ok(())
 }
+
+#[test]
+fn test_split_level() {
+    assert_eq!( split_level( "error: cannot run node.js" ), ("error", "cannot run node.js") );
+    assert_eq!( split_level( "warning:  `--release` is ignored" ), ("warning", "`--release` is ignored") );
+    assert_eq!( split_level( "    Finished in 1.5s" ), ("info", "Finished in 1.5s") );
+    assert_eq!( split_level( "errors: 2" ), ("info", "errors: 2") );
+}
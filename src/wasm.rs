@@ -1,8 +1,17 @@
 use std::path::{Path, PathBuf};
 use std::fs::{self, File};
 use std::io::Write;
+use std::process::{Command, exit};
+use std::sync::{Once, ONCE_INIT};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::env;
+use std::mem;
 
+use sha2;
+use digest::Digest;
 use parity_wasm;
+use parity_wasm::elements as pw;
+use parity_wasm::elements::{Serialize, Deserialize};
 use cargo_shim::BuildConfig;
 
 use wasm_gc;
@@ -12,27 +21,383 @@ use wasm_inline_js;
 use wasm_export_main;
 use wasm_export_table;
 use wasm_hook_grow;
+use wasm_normalize;
 use wasm_intrinsics;
-use wasm_runtime;
+use wasm_runtime::{self, RuntimeKind};
+use wasm_typescript;
+use utils::check_if_command_exists;
 
-pub fn process_wasm_file< P: AsRef< Path > + ?Sized >( build: &BuildConfig, artifact: &P ) -> Option< PathBuf > {
-    if !build.triplet.as_ref().map( |triplet| triplet == "wasm32-unknown-unknown" ).unwrap_or( false ) {
+#[derive(Clone, Debug)]
+pub struct BuildInfo {
+    pub version: String,
+    pub crate_root: PathBuf
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ProcessOptions {
+    pub build_info: Option< BuildInfo >,
+    pub wasm_opt_args: Option< Vec< String > >,
+    /// Runs `wasm-opt -O<level>`, if it's installed.
+    pub wasm_opt_level: Option< String >,
+    /// Whether the custom sections with debug info are kept instead of stripped.
+    pub keep_debug: bool,
+    pub normalize: bool,
+    pub allowed_imports: Option< Vec< String > >,
+    pub sign_command: Option< Vec< String > >,
+    /// The contents of the `host-imports` file from `Web.toml`.
+    pub host_imports: Option< String >,
+    /// Whether the `.js` loader isn't generated, e.g. with `--emit wasm`.
+    pub skip_js: bool,
+    /// Whether to also generate a `.d.ts` file next to the `.js` file.
+    pub typescript: bool,
+    pub runtime: RuntimeKind,
+    /// The name the module is exported under instead of the one derived from its filename.
+    pub module_name: Option< String >,
+    /// Whether to also write an `index.html` which loads the `.js` file.
+    pub generate_html: bool,
+    /// Whether that `index.html` replaces one which already exists.
+    pub overwrite_html: bool,
+    /// The Content Security Policy which that `index.html` declares.
+    pub content_security_policy: Option< String >
+}
+
+const BUILD_INFO_SECTION: &'static str = "cargo-web-build-info";
+
+fn git_hash( crate_root: &Path ) -> Option< String > {
+    let output = Command::new( "git" )
+        .arg( "rev-parse" )
+        .arg( "HEAD" )
+        .current_dir( crate_root )
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
         return None;
     }
 
+    Some( String::from_utf8_lossy( &output.stdout ).trim().to_owned() )
+}
+
+fn build_info_payload( build_info: &BuildInfo ) -> String {
+    // Honor `SOURCE_DATE_EPOCH` so that reproducible builds stay reproducible.
+    let timestamp = env::var( "SOURCE_DATE_EPOCH" ).ok()
+        .and_then( |value| value.parse::< u64 >().ok() )
+        .unwrap_or_else( || SystemTime::now().duration_since( UNIX_EPOCH ).unwrap().as_secs() );
+
+    let payload = json!({
+        "version": build_info.version,
+        "timestamp": timestamp,
+        "git_hash": git_hash( &build_info.crate_root )
+    });
+
+    payload.to_string()
+}
+
+fn custom_section( name: &str, payload: &[u8] ) -> pw::CustomSection {
+    let mut body = Vec::new();
+    pw::VarUint32::from( name.len() ).serialize( &mut body ).unwrap();
+    body.extend_from_slice( name.as_bytes() );
+    body.extend_from_slice( payload );
+
+    let mut section = Vec::new();
+    pw::VarUint32::from( body.len() ).serialize( &mut section ).unwrap();
+    section.extend_from_slice( &body );
+
+    pw::CustomSection::deserialize( &mut section.as_slice() ).unwrap()
+}
+
+// Takes out every custom section besides the `name` one, which
+// is the only one our processing passes know how to update.
+fn take_debug_sections( module: &mut pw::Module ) -> Vec< pw::Section > {
+    let (debug_sections, sections) = mem::replace( module.sections_mut(), Vec::new() ).into_iter().partition( |section| {
+        match *section {
+            pw::Section::Custom( ref section ) => section.name() != "name",
+            _ => false
+        }
+    });
+
+    *module.sections_mut() = sections;
+    debug_sections
+}
+
+fn is_dwarf_section( section: &pw::Section ) -> bool {
+    match *section {
+        pw::Section::Custom( ref section ) => section.name().starts_with( ".debug_" ),
+        _ => false
+    }
+}
+
+// Tells which setting from `Web.toml` the arguments come from.
+fn run_wasm_opt( path: &Path, args: &[String], setting: &str ) {
+    println_err!( "    Running wasm-opt on {:?}...", path.file_name().unwrap() );
+    let output = Command::new( "wasm-opt" )
+        .args( args )
+        .arg( path )
+        .arg( "-o" )
+        .arg( path )
+        .output();
+
+    let output = match output {
+        Ok( output ) => output,
+        Err( error ) => {
+            println_err!( "error: failed to launch `wasm-opt`: {}", error );
+            println_err_cont!( "       you need to have it installed and in your PATH to use `{}` from your `Web.toml`", setting );
+            exit( 101 );
+        }
+    };
+
+    if !output.status.success() {
+        println_err!( "error: `wasm-opt` failed with the following arguments from `{}` in your `Web.toml`: {:?}", setting, args );
+        let stderr = String::from_utf8_lossy( &output.stderr );
+        for line in stderr.lines() {
+            println_err_cont!( "    {}", line );
+        }
+        exit( 101 );
+    }
+}
+
+// Marks the `.wasm` file as processed in case none of the files generated
+// from it are newer than it, since the processing can't be done twice.
+fn processed_marker_path( artifact: &Path ) -> PathBuf {
+    let mut filename = artifact.file_name().unwrap().to_owned();
+    filename.push( ".processed" );
+    artifact.with_file_name( filename )
+}
+
+fn signature_path( artifact: &Path ) -> PathBuf {
+    let mut filename = artifact.file_name().unwrap().to_owned();
+    filename.push( ".sig" );
+    artifact.with_file_name( filename )
+}
+
+// The `{input}` and `{output}` placeholders in the command get replaced
+// with the artifact's path and with the path where the signature is
+// expected to be written; without `{input}` the artifact's path is
+// passed as the last argument.
+fn sign_command_args( command: &[String], input: &Path, output: &Path ) -> Vec< String > {
+    let input = input.to_string_lossy();
+    let output = output.to_string_lossy();
+    let mut args: Vec< String > = command.iter().map( |arg| arg.replace( "{input}", &input ).replace( "{output}", &output ) ).collect();
+    if !command.iter().any( |arg| arg.contains( "{input}" ) ) {
+        args.push( input.into_owned() );
+    }
+
+    args
+}
+
+pub fn sign_artifact( path: &Path, command: &[String] ) -> Option< PathBuf > {
+    if !path.extension().map( |ext| ext == "wasm" ).unwrap_or( false ) || command.is_empty() {
+        return None;
+    }
+
+    let signature = signature_path( path );
+    if signature.exists() {
+        let signature_mtime = fs::metadata( &signature ).unwrap().modified().unwrap();
+        let wasm_mtime = fs::metadata( path ).unwrap().modified().unwrap();
+        if signature_mtime >= wasm_mtime {
+            return Some( signature );
+        }
+    }
+
+    println_err!( "    Signing {:?}...", path.file_name().unwrap() );
+    let args = sign_command_args( command, path, &signature );
+    let status = Command::new( &args[ 0 ] ).args( &args[ 1.. ] ).status();
+    match status {
+        Ok( ref status ) if status.success() => {},
+        Ok( status ) => {
+            println_err!( "error: `sign-command` from your `Web.toml` failed with {}", status );
+            exit( 101 );
+        },
+        Err( error ) => {
+            println_err!( "error: failed to launch `sign-command` from your `Web.toml`: {}", error );
+            exit( 101 );
+        }
+    }
+
+    if !signature.exists() {
+        println_err!( "error: `sign-command` from your `Web.toml` didn't write the signature to {:?}", signature );
+        exit( 101 );
+    }
+
+    Some( signature )
+}
+
+// An entry allows either a whole module (`env`) or
+// a single import from a module (`env.__web_on_grow`).
+fn is_import_allowed( allowed_imports: &[String], module: &str, field: &str ) -> bool {
+    allowed_imports.iter().any( |allowed| {
+        if let Some( dot ) = allowed.find( '.' ) {
+            &allowed[ ..dot ] == module && &allowed[ dot + 1.. ] == field
+        } else {
+            allowed == module
+        }
+    })
+}
+
+pub fn find_disallowed_imports( path: &Path, allowed_imports: &[String] ) -> Vec< String > {
+    let module = parity_wasm::deserialize_file( path ).unwrap();
+    let entries = match module.import_section() {
+        Some( section ) => section.entries(),
+        None => return Vec::new()
+    };
+
+    entries.iter()
+        .filter( |entry| !is_import_allowed( allowed_imports, entry.module(), entry.field() ) )
+        .map( |entry| format!( "{}.{}", entry.module(), entry.field() ) )
+        .collect()
+}
+
+/// Adds a source, e.g. a nonce, to the `script-src` of a Content Security Policy.
+/// If the policy has no `script-src` then scripts fall back to `default-src`,
+/// so that's what the source has to be added to instead.
+pub fn add_script_source( policy: &str, source: &str ) -> String {
+    let mut directives: Vec< String > = policy.split( ';' ).map( |directive| directive.trim().to_owned() ).filter( |directive| !directive.is_empty() ).collect();
+    if let Some( directive ) = directives.iter_mut().find( |directive| directive.split_whitespace().next() == Some( "script-src" ) ) {
+        directive.push_str( " " );
+        directive.push_str( source );
+        return directives.join( "; " );
+    }
+
+    let default_sources = directives.iter()
+        .find( |directive| directive.split_whitespace().next() == Some( "default-src" ) )
+        .map( |directive| directive[ "default-src".len().. ].trim().to_owned() );
+
+    if let Some( default_sources ) = default_sources {
+        // `'none'` can't be combined with any other source.
+        directives.push( format!( "script-src {} {}", default_sources, source ).replace( "'none' ", "" ) );
+    }
+
+    directives.join( "; " )
+}
+
+fn base64( bytes: &[u8] ) -> String {
+    const ALPHABET: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::new();
+    for chunk in bytes.chunks( 3 ) {
+        let value = (chunk[ 0 ] as u32) << 16 | (*chunk.get( 1 ).unwrap_or( &0 ) as u32) << 8 | *chunk.get( 2 ).unwrap_or( &0 ) as u32;
+        for nth in 0..4 {
+            if nth <= chunk.len() {
+                output.push( ALPHABET[ (value >> (18 - nth * 6) & 0x3F) as usize ] as char );
+            } else {
+                output.push( '=' );
+            }
+        }
+    }
+
+    output
+}
+
+// Allows a given inline script without allowing every other one.
+fn script_hash_source( script: &str ) -> String {
+    let mut hasher = sha2::Sha256::default();
+    hasher.input( script.as_bytes() );
+    format!( "'sha256-{}'", base64( &hasher.result() ) )
+}
+
+/// Adds a `<meta>` tag with the Content Security Policy to the page's `<head>`;
+/// returns `None` if the page doesn't have one.
+pub fn with_content_security_policy( html: &str, policy: &str ) -> Option< String > {
+    if !html.contains( "<head>" ) {
+        return None;
+    }
+
+    let tag = format!( "<head>\n    <meta http-equiv=\"Content-Security-Policy\" content=\"{}\" />", policy.replace( '"', "&quot;" ) );
+    Some( html.replacen( "<head>", &tag, 1 ) )
+}
+
+pub fn index_html( js_filename: &str, runtime: RuntimeKind, policy: Option< &str > ) -> String {
+    let (script, inline_script) = match runtime {
+        RuntimeKind::LibraryEs6 => {
+            let code = format!( "import init from \"./{}\"; init();", js_filename );
+            (format!( "<script type=\"module\">{}</script>", code ), Some( code ))
+        },
+        RuntimeKind::Standalone | RuntimeKind::WebExtension => (format!( "<script src=\"{}\"></script>", js_filename ), None)
+    };
+
+    let html = format!( r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8" />
+</head>
+<body>
+    {}
+</body>
+</html>
+"#, script );
+
+    match policy {
+        Some( policy ) => {
+            let policy = match inline_script {
+                Some( code ) => add_script_source( policy, &script_hash_source( &code ) ),
+                None => policy.to_owned()
+            };
+
+            with_content_security_policy( &html, &policy ).unwrap()
+        },
+        None => html
+    }
+}
+
+/// Fills in the `{{js}}` and `{{wasm}}` placeholders of a custom `index.html`
+/// template with the artifacts' paths; anything else is kept verbatim.
+pub fn render_index_template( template: &str, js_path: &str, wasm_path: Option< &str > ) -> String {
+    let html = template.replace( "{{js}}", js_path );
+    match wasm_path {
+        Some( wasm_path ) => html.replace( "{{wasm}}", wasm_path ),
+        None => html
+    }
+}
+
+/// Writes an `index.html` loading the `.js` file generated for the `.wasm` file next to it.
+/// An existing `index.html` is left alone, unless `overwrite` is set.
+fn write_index_html( wasm_path: &Path, runtime: RuntimeKind, policy: Option< &str >, overwrite: bool ) -> PathBuf {
+    let html_path = wasm_path.with_file_name( "index.html" );
+    if overwrite || !html_path.exists() {
+        let js_path = wasm_path.with_extension( "js" );
+        let js_filename = js_path.file_name().unwrap().to_string_lossy().into_owned();
+        let mut fp = File::create( &html_path ).unwrap();
+        fp.write_all( index_html( &js_filename, runtime, policy ).as_bytes() ).unwrap();
+    }
+
+    html_path
+}
+
+pub fn process_wasm_file< P: AsRef< Path > + ?Sized >( build: &BuildConfig, options: &ProcessOptions, artifact: &P ) -> Vec< PathBuf > {
+    if !build.triplet.as_ref().map( |triplet| triplet == "wasm32-unknown-unknown" ).unwrap_or( false ) {
+        return Vec::new();
+    }
+
     let path = artifact.as_ref();
     if !path.extension().map( |ext| ext == "wasm" ).unwrap_or( false ) {
-        return None;
+        return Vec::new();
     }
 
     let js_path = path.with_extension( "js" );
-    if js_path.exists() {
-        let js_mtime = fs::metadata( &js_path ).unwrap().modified().unwrap();
-        let wasm_mtime = fs::metadata( path ).unwrap().modified().unwrap();
-        if js_mtime >= wasm_mtime {
-            // We've already ran; nothing to do here.
-            return None;
+    let dts_path = path.with_extension( "d.ts" );
+    let marker_path = processed_marker_path( path );
+    let mut outputs = Vec::new();
+    if !options.skip_js {
+        outputs.push( js_path.clone() );
+    }
+    if options.typescript {
+        outputs.push( dts_path.clone() );
+    }
+
+    let wasm_mtime = fs::metadata( path ).unwrap().modified().unwrap();
+    let is_fresh = |output: &PathBuf| {
+        fs::metadata( output ).map( |metadata| metadata.modified().unwrap() >= wasm_mtime ).unwrap_or( false )
+    };
+
+    // Older versions didn't write the marker, but the `.js` file is always written after the processing.
+    if is_fresh( &marker_path ) || is_fresh( &js_path ) {
+        if let Some( missing ) = outputs.iter().find( |output| !is_fresh( output ) ) {
+            println_err!( "error: {:?} was already processed by a build which didn't generate {:?}", path.file_name().unwrap(), missing.file_name().unwrap() );
+            println_err_cont!( "       touch one of your crate's source files so that it gets rebuilt" );
+            exit( 101 );
         }
+
+        // We've already ran; nothing to do here.
+        return with_index_html( path, options, outputs );
     }
 
     println_err!( "    Garbage collecting {:?}...", path.file_name().unwrap() );
@@ -40,21 +405,164 @@ pub fn process_wasm_file< P: AsRef< Path > + ?Sized >( build: &BuildConfig, arti
 
     println_err!( "    Processing {:?}...", path.file_name().unwrap() );
     let mut module = parity_wasm::deserialize_file( &path ).unwrap();
+    let debug_sections = take_debug_sections( &mut module );
     let mut ctx = Context::from_module( module );
     let snippets = wasm_inline_js::process_and_extract( &mut ctx );
     let intrinsics = wasm_intrinsics::process( &mut ctx );
     wasm_export_main::process( &mut ctx );
     wasm_export_table::process( &mut ctx );
     wasm_hook_grow::process( &mut ctx );
+    if options.normalize {
+        wasm_normalize::sort_imports( &mut ctx );
+    }
+
+    let declarations = if options.typescript {
+        Some( wasm_typescript::generate( &ctx ) )
+    } else {
+        None
+    };
+
     module = ctx.into_module();
+    if options.normalize {
+        wasm_normalize::sort_exports( &mut module );
+    }
+
+    // This has to be done last as the processing passes
+    // don't preserve any unknown custom sections.
+    if options.keep_debug {
+        // The offsets in the DWARF sections point into the code as rustc emitted it,
+        // which the garbage collection and the passes above have rewritten, so
+        // keeping them would only ship debug info which is silently wrong.
+        let (dwarf_sections, other_sections): (Vec< _ >, Vec< _ >) = debug_sections.into_iter().partition( is_dwarf_section );
+        if !dwarf_sections.is_empty() {
+            static WARNING: Once = ONCE_INIT;
+            WARNING.call_once( || {
+                println_err!( "warning: the DWARF sections can't be kept with `--keep-debug` since the `.wasm` files are rewritten after they're compiled; only the function names are kept" );
+            });
+        }
+
+        module.sections_mut().extend( other_sections );
+    }
+
+    if let Some( ref build_info ) = options.build_info {
+        let payload = build_info_payload( build_info );
+        module.sections_mut().push( pw::Section::Custom( custom_section( BUILD_INFO_SECTION, payload.as_bytes() ) ) );
+    }
 
     parity_wasm::serialize_to_file( path, module ).unwrap();
 
-    let all_snippets: Vec< _ > = snippets.into_iter().chain( intrinsics.into_iter() ).collect();
-    let js = wasm_runtime::generate_js( path, &all_snippets );
-    let mut fp = File::create( &js_path ).unwrap();
-    fp.write_all( js.as_bytes() ).unwrap();
+    // Unlike with `wasm-opt-args` the optimizations are optional, so
+    // a missing `wasm-opt` is only worth a warning, and only once.
+    if let Some( ref level ) = options.wasm_opt_level {
+        if check_if_command_exists( "wasm-opt", None ) {
+            let mut args = vec![ format!( "-O{}", level ) ];
+            if options.keep_debug {
+                args.push( "-g".to_owned() );
+            }
+
+            run_wasm_opt( path, &args, "wasm-opt-level" );
+        } else {
+            static WARNING: Once = ONCE_INIT;
+            WARNING.call_once( || {
+                println_err!( "warning: `wasm-opt` isn't installed, so `wasm-opt-level` from your `Web.toml` is ignored" );
+            });
+        }
+    }
+
+    if let Some( ref args ) = options.wasm_opt_args {
+        run_wasm_opt( path, args, "wasm-opt-args" );
+    }
+
+    if !options.skip_js {
+        let all_snippets: Vec< _ > = snippets.into_iter().chain( intrinsics.into_iter() ).collect();
+        let js = wasm_runtime::generate_js(
+            path,
+            &all_snippets,
+            options.host_imports.as_ref().map( |code| code.as_str() ),
+            options.runtime,
+            options.module_name.as_ref().map( |name| name.as_str() )
+        );
+        let mut fp = File::create( &js_path ).unwrap();
+        fp.write_all( js.as_bytes() ).unwrap();
+    }
+
+    if let Some( declarations ) = declarations {
+        let mut fp = File::create( &dts_path ).unwrap();
+        fp.write_all( declarations.as_bytes() ).unwrap();
+    }
+
+    File::create( &marker_path ).unwrap();
 
     println_err!( "    Finished processing of {:?}!", path.file_name().unwrap() );
-    Some( js_path )
+    with_index_html( path, options, outputs )
+}
+
+fn with_index_html( wasm_path: &Path, options: &ProcessOptions, mut outputs: Vec< PathBuf > ) -> Vec< PathBuf > {
+    if options.generate_html {
+        let policy = options.content_security_policy.as_ref().map( |policy| policy.as_str() );
+        outputs.push( write_index_html( wasm_path, options.runtime, policy, options.overwrite_html ) );
+    }
+
+    outputs
+}
+
+#[test]
+fn test_is_import_allowed() {
+    let allowed_imports = vec![ "env".to_owned(), "global.NaN".to_owned() ];
+    assert!( is_import_allowed( &allowed_imports, "env", "__web_on_grow" ) );
+    assert!( is_import_allowed( &allowed_imports, "global", "NaN" ) );
+    assert!( !is_import_allowed( &allowed_imports, "global", "Infinity" ) );
+    assert!( !is_import_allowed( &allowed_imports, "wasi", "fd_write" ) );
+}
+
+#[test]
+fn test_sign_command_args() {
+    let input = Path::new( "/target/app.wasm" );
+    let output = signature_path( input );
+    assert_eq!( output, Path::new( "/target/app.wasm.sig" ) );
+
+    let command = vec![ "gpg".to_owned(), "--detach-sign".to_owned(), "--output".to_owned(), "{output}".to_owned() ];
+    assert_eq!( sign_command_args( &command, input, &output ), vec![ "gpg", "--detach-sign", "--output", "/target/app.wasm.sig", "/target/app.wasm" ] );
+
+    let command = vec![ "sign".to_owned(), "{input}".to_owned(), "{output}".to_owned() ];
+    assert_eq!( sign_command_args( &command, input, &output ), vec![ "sign", "/target/app.wasm", "/target/app.wasm.sig" ] );
+}
+
+#[test]
+fn test_index_html() {
+    assert!( index_html( "app.js", RuntimeKind::Standalone, None ).contains( "<script src=\"app.js\"></script>" ) );
+    assert!( index_html( "app.js", RuntimeKind::LibraryEs6, None ).contains( "import init from \"./app.js\"; init();" ) );
+    assert!( !index_html( "app.js", RuntimeKind::Standalone, None ).contains( "Content-Security-Policy" ) );
+
+    let html = index_html( "app.js", RuntimeKind::Standalone, Some( "default-src 'self'" ) );
+    assert!( html.contains( "<meta http-equiv=\"Content-Security-Policy\" content=\"default-src 'self'\" />" ) );
+
+    let html = index_html( "app.js", RuntimeKind::LibraryEs6, Some( "default-src 'self'" ) );
+    let source = script_hash_source( "import init from \"./app.js\"; init();" );
+    assert!( html.contains( &format!( "content=\"default-src 'self'; script-src 'self' {}\"", source ) ) );
+}
+
+#[test]
+fn test_add_script_source() {
+    assert_eq!( add_script_source( "default-src 'self'; script-src 'self' 'wasm-unsafe-eval'", "'nonce-abc'" ), "default-src 'self'; script-src 'self' 'wasm-unsafe-eval' 'nonce-abc'" );
+    assert_eq!( add_script_source( "default-src 'self';", "'nonce-abc'" ), "default-src 'self'; script-src 'self' 'nonce-abc'" );
+    assert_eq!( add_script_source( "default-src 'none'", "'nonce-abc'" ), "default-src 'none'; script-src 'nonce-abc'" );
+    assert_eq!( add_script_source( "img-src *", "'nonce-abc'" ), "img-src *" );
+}
+
+#[test]
+fn test_base64() {
+    assert_eq!( base64( b"" ), "" );
+    assert_eq!( base64( b"f" ), "Zg==" );
+    assert_eq!( base64( b"fo" ), "Zm8=" );
+    assert_eq!( base64( b"foo" ), "Zm9v" );
+    assert_eq!( script_hash_source( "" ), "'sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU='" );
+}
+
+#[test]
+fn test_render_index_template() {
+    let template = "<link rel=\"preload\" href=\"{{wasm}}\"><script src=\"{{js}}\"></script>";
+    assert_eq!( render_index_template( template, "app.js", Some( "app.wasm" ) ), "<link rel=\"preload\" href=\"app.wasm\"><script src=\"app.js\"></script>" );
+    assert_eq!( render_index_template( template, "app.js", None ), "<link rel=\"preload\" href=\"{{wasm}}\"><script src=\"app.js\"></script>" );
+    assert_eq!( render_index_template( "<p>Hello</p>", "app.js", None ), "<p>Hello</p>" );
 }
@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use parity_wasm;
+use parity_wasm::elements as pw;
+use parity_wasm::elements::{Serialize, Deserialize};
+
+use wasm_context::decode_name_map;
+
+const UNKNOWN_CRATE: &'static str = "[unknown]";
+
+// The names are already demangled by `wasm_gc`, so they look like
+// `core::fmt::write::h...` or `<serde_json::Value as core::fmt::Debug>::fmt::h...`;
+// trait impls are attributed to the crate of the type they're implemented for.
+fn crate_of( name: &str ) -> Option< &str > {
+    let name = name.trim_left_matches( |ch| ch == '<' || ch == '&' || ch == '*' );
+    let name = name.trim_left_matches( "mut " ).trim_left_matches( "const " );
+    let prefix = match name.find( "::" ) {
+        Some( index ) => &name[ ..index ],
+        None => return None
+    };
+
+    if prefix.is_empty() || !prefix.chars().all( |ch| ch.is_alphanumeric() || ch == '_' ) {
+        return None;
+    }
+
+    Some( prefix )
+}
+
+fn function_names( module: &pw::Module ) -> Option< BTreeMap< u32, String > > {
+    let section = module.sections().iter().filter_map( |section| {
+        match section {
+            &pw::Section::Custom( ref section ) if section.name() == "name" => Some( section ),
+            _ => None
+        }
+    }).next()?;
+
+    let mut names = BTreeMap::new();
+    let mut p: &[u8] = section.payload();
+    while p.len() > 0 {
+        let kind = u8::from( pw::VarUint7::deserialize( &mut p ).ok()? );
+        let payload_length = u32::from( pw::VarUint32::deserialize( &mut p ).ok()? );
+        let (mut payload, next_p) = p.split_at( payload_length as usize );
+        p = next_p;
+
+        if kind == 1 {
+            decode_name_map( &mut payload, |index, name| {
+                names.insert( index, name.to_owned() );
+            }).ok()?;
+        }
+    }
+
+    Some( names )
+}
+
+/// Returns how many bytes of code each crate contributes to the given
+/// `.wasm` file, largest first, or `None` if it has no name section.
+pub fn code_size_by_crate( path: &Path ) -> Option< Vec< (String, usize) > > {
+    let module = parity_wasm::deserialize_file( path ).unwrap();
+    let names = function_names( &module )?;
+    let imported_function_count = module.import_section().map( |section| {
+        section.entries().iter().filter( |entry| {
+            match entry.external() {
+                &pw::External::Function( _ ) => true,
+                _ => false
+            }
+        }).count()
+    }).unwrap_or( 0 );
+
+    let mut sizes = BTreeMap::new();
+    if let Some( section ) = module.code_section() {
+        for (index, body) in section.bodies().iter().enumerate() {
+            let mut buffer = Vec::new();
+            body.clone().serialize( &mut buffer ).unwrap();
+
+            let function_index = (imported_function_count + index) as u32;
+            let name = names.get( &function_index ).and_then( |name| crate_of( name ) ).unwrap_or( UNKNOWN_CRATE );
+            *sizes.entry( name.to_owned() ).or_insert( 0 ) += buffer.len();
+        }
+    }
+
+    let mut sizes: Vec< (String, usize) > = sizes.into_iter().collect();
+    sizes.sort_by( |lhs, rhs| rhs.1.cmp( &lhs.1 ).then_with( || lhs.0.cmp( &rhs.0 ) ) );
+    Some( sizes )
+}
+
+pub fn print_code_size_by_crate( path: &Path ) {
+    let sizes = match code_size_by_crate( path ) {
+        Some( sizes ) => sizes,
+        None => {
+            println_err!( "warning: {:?} has no name section, so its size can't be broken down by crate", path.file_name().unwrap() );
+            return;
+        }
+    };
+
+    let total: usize = sizes.iter().map( |&(_, size)| size ).sum();
+    println_err!( "    Code size of {:?} by crate ({} bytes in total):", path.file_name().unwrap(), total );
+    for (name, size) in sizes {
+        let percentage = if total == 0 { 0.0 } else { size as f64 * 100.0 / total as f64 };
+        println_err!( "    {:>10}  {:>5.1}%  {}", size, percentage, name );
+    }
+}
+
+#[test]
+fn test_crate_of() {
+    assert_eq!( crate_of( "core::fmt::write::h5a2d3fc3bd1f479c" ), Some( "core" ) );
+    assert_eq!( crate_of( "<serde_json::value::Value as core::fmt::Debug>::fmt::h8e9f0ab3ac2c8d5e" ), Some( "serde_json" ) );
+    assert_eq!( crate_of( "<&'a T as core::fmt::Display>::fmt::h0123456789abcdef" ), None );
+    assert_eq!( crate_of( "memcpy" ), None );
+}
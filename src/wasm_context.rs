@@ -73,6 +73,10 @@ impl Export {
             names: vec![ name ]
         }
     }
+
+    pub fn names( &self ) -> &[String] {
+        &self.names
+    }
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -286,7 +290,7 @@ fn take< T: Default >( reference: &mut T ) -> T {
 }
 
 // This is based on code from wasm-gc.
-fn decode_name_map< F: for< 'a > FnMut( u32, &'a str ) > ( p: &mut &[u8], mut callback: F ) -> Result< (), pw::Error > {
+pub fn decode_name_map< F: for< 'a > FnMut( u32, &'a str ) > ( p: &mut &[u8], mut callback: F ) -> Result< (), pw::Error > {
     let count = u32::from( pw::VarUint32::deserialize( p )? );
     for _ in 0..count {
         let index = u32::from( pw::VarUint32::deserialize( p )? );
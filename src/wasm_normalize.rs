@@ -0,0 +1,98 @@
+use std::cmp::Ordering;
+
+use parity_wasm::elements as pw;
+
+use wasm_context::{
+    FunctionKind,
+    Context
+};
+
+fn import_key( function: &FunctionKind ) -> Option< (&str, &str) > {
+    match function {
+        &FunctionKind::Import { ref import, .. } => Some( (&import.module, &import.field) ),
+        &FunctionKind::Definition { .. } => None
+    }
+}
+
+// The imports end up sorted in front of the definitions, which keep
+// their relative order; all of the function indices get renumbered
+// when the module is serialized anyway.
+pub fn sort_imports( ctx: &mut Context ) {
+    ctx.functions.sort_by( |_, lhs, _, rhs| {
+        match (import_key( lhs ), import_key( rhs )) {
+            (Some( lhs ), Some( rhs )) => lhs.cmp( &rhs ),
+            (Some( _ ), None) => Ordering::Less,
+            (None, Some( _ )) => Ordering::Greater,
+            (None, None) => Ordering::Equal
+        }
+    });
+}
+
+pub fn sort_exports( module: &mut pw::Module ) {
+    for section in module.sections_mut() {
+        if let &mut pw::Section::Export( ref mut section ) = section {
+            section.entries_mut().sort_by( |lhs, rhs| lhs.field().cmp( rhs.field() ) );
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_import( ctx: &mut Context, module: &str, field: &str ) {
+    use wasm_context::{Export, FnTy, Import};
+
+    let type_index = ctx.get_or_add_fn_type( FnTy { params: vec![], return_type: None } );
+    ctx.add_function( FunctionKind::Import {
+        export: Export::none(),
+        type_index,
+        name: None,
+        import: Import {
+            module: module.to_owned(),
+            field: field.to_owned()
+        }
+    });
+}
+
+#[cfg(test)]
+fn test_definition( ctx: &mut Context, name: &str ) {
+    use wasm_context::{Export, FnTy};
+
+    let type_index = ctx.get_or_add_fn_type( FnTy { params: vec![], return_type: None } );
+    ctx.add_function( FunctionKind::Definition {
+        export: Export::some( name.to_owned() ),
+        type_index,
+        name: Some( name.to_owned() ),
+        locals: vec![],
+        opcodes: vec![]
+    });
+}
+
+#[test]
+fn test_sort_imports() {
+    let mut ctx = Context::new();
+    test_import( &mut ctx, "env", "b" );
+    test_definition( &mut ctx, "main" );
+    test_import( &mut ctx, "env", "a" );
+    test_definition( &mut ctx, "helper" );
+    test_import( &mut ctx, "abc", "z" );
+
+    sort_imports( &mut ctx );
+    let order: Vec< String > = ctx.functions.values().map( |function| match function {
+        &FunctionKind::Import { ref import, .. } => format!( "{}.{}", import.module, import.field ),
+        &FunctionKind::Definition { ref name, .. } => name.clone().unwrap()
+    }).collect();
+
+    assert_eq!( order, vec![ "abc.z", "env.a", "env.b", "main", "helper" ] );
+}
+
+#[test]
+fn test_sort_exports() {
+    let mut ctx = Context::new();
+    test_definition( &mut ctx, "zebra" );
+    test_definition( &mut ctx, "apple" );
+    test_definition( &mut ctx, "mango" );
+
+    let mut module = ctx.into_module();
+    sort_exports( &mut module );
+    let fields: Vec< &str > = module.export_section().unwrap().entries().iter().map( |entry| entry.field() ).collect();
+    assert_eq!( fields, vec![ "apple", "mango", "zebra" ] );
+}
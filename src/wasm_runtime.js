@@ -1,5 +1,9 @@
 "use strict";
 
+{{#if es6}}
+// Resolves to the module's exports once the `.wasm` file is loaded.
+export default function() {
+{{else}}
 if( typeof Rust === 'undefined' ) {
     var Rust = {};
 }
@@ -13,6 +17,7 @@ if( typeof Rust === 'undefined' ) {
         factory();
     }
 }( this, function() {
+{{/if}}
     const Module = {};
     let HEAP8 = null;
     let HEAP16 = null;
@@ -23,7 +28,8 @@ if( typeof Rust === 'undefined' ) {
     let HEAPF32 = null;
     let HEAPF64 = null;
 
-    Object.defineProperty( Module, 'nodejs', { value: (typeof window === 'undefined') } );
+    // Web Workers don't have a `window` either, but they do have `importScripts`.
+    Object.defineProperty( Module, 'nodejs', { value: (typeof window === 'undefined' && typeof importScripts !== 'function') } );
     Object.defineProperty( Module, 'exports', { value: {} } );
 
     const __imports = {
@@ -43,22 +49,56 @@ if( typeof Rust === 'undefined' ) {
         }
     };
 
+    // The `host-imports` file from `Web.toml` is the body of a function
+    // which returns extra imports, as `{ module: { field: function } }`.
+    (function( host_imports ) {
+        for( const module in host_imports ) {
+            __imports[ module ] = Object.assign( __imports[ module ] || {}, host_imports[ module ] );
+        }
+    }( (function() {
+        {{{host_imports}}}
+    }()) ));
+
     function __load( instance ) {
         Object.defineProperty( Module, 'instance', { value: instance } );
         Object.defineProperty( Module, 'web_malloc', { value: Module.instance.exports.__web_malloc } );
         Object.defineProperty( Module, 'web_free', { value: Module.instance.exports.__web_free } );
         Object.defineProperty( Module, 'web_table', { value: Module.instance.exports.__web_table } );
 
+        {{#unless es6}}
         if( typeof module !== 'undefined' && module.exports ) {
             module.exports = Module.exports;
         } else {
             Rust.{{{module_name}}}.exports = Module.exports;
         }
+        {{/unless}}
 
         __imports.env.__web_on_grow();
-        Module.instance.exports.__web_main();
+        if( Module.nodejs && process.env.CARGO_WEB_TEST_REPORT ) {
+            // The test harness can't print anything on this target, so
+            // this is how `cargo web test` finds out how the run went.
+            try {
+                Module.instance.exports.__web_main();
+            } catch( error ) {
+                console.log( "cargo-web-test-report: " + JSON.stringify( { status: "failed", message: String( error ) } ) );
+                process.exit( 101 );
+            }
+            console.log( "cargo-web-test-report: " + JSON.stringify( { status: "ok" } ) );
+        } else {
+            Module.instance.exports.__web_main();
+        }
     }
 
+    {{#if es6}}
+    return fetch( new URL( "{{{wasm_filename}}}", import.meta.url ) )
+        .then( response => response.arrayBuffer() )
+        .then( bytes => WebAssembly.instantiate( bytes, __imports ) )
+        .then( results => {
+            __load( results.instance );
+            return Module.exports;
+        });
+}
+{{else}}
     if( Module.nodejs ) {
         const fs = require( 'fs' );
         const path = require( 'path' );
@@ -69,7 +109,14 @@ if( typeof Rust === 'undefined' ) {
         __load( instance );
         return Module.exports;
     } else {
-        const __promise = fetch( "{{{wasm_filename}}}" )
+        {{#if web_extension}}
+        // Relative URLs would be resolved against the page a content script runs in,
+        // so this expects the `.wasm` file to be in the root of the extension.
+        const __url = (typeof browser !== 'undefined' ? browser : chrome).runtime.getURL( "{{{wasm_filename}}}" );
+        {{else}}
+        const __url = "{{{wasm_filename}}}";
+        {{/if}}
+        const __promise = fetch( __url )
             .then( response => response.arrayBuffer() )
             .then( bytes => WebAssembly.instantiate( bytes, __imports ) )
             .then( results => {
@@ -86,3 +133,4 @@ if( typeof Rust === 'undefined' ) {
         return __promise;
     }
 }));
+{{/if}}
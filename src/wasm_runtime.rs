@@ -52,9 +52,46 @@ fn to_js_identifier( string: &str ) -> String {
 
 static RUNTIME_TEMPLATE: &str = include_str!( "wasm_runtime.js" );
 
-pub fn generate_js( wasm_path: &Path, snippets: &[JsSnippet] ) -> String {
+/// Which flavor of the `.js` loader gets generated, as selected with `--runtime`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RuntimeKind {
+    /// Sets a global and also works as an AMD or a CommonJS module.
+    Standalone,
+    /// An ES module whose default export loads the `.wasm` file.
+    LibraryEs6,
+    /// Like `Standalone`, but loads the `.wasm` file from the extension's root.
+    WebExtension
+}
+
+impl Default for RuntimeKind {
+    fn default() -> Self {
+        RuntimeKind::Standalone
+    }
+}
+
+impl RuntimeKind {
+    pub fn from_str( name: &str ) -> Option< Self > {
+        match name {
+            "standalone" => Some( RuntimeKind::Standalone ),
+            "library-es6" => Some( RuntimeKind::LibraryEs6 ),
+            "web-extension" => Some( RuntimeKind::WebExtension ),
+            _ => None
+        }
+    }
+}
+
+pub fn generate_js(
+    wasm_path: &Path,
+    snippets: &[JsSnippet],
+    host_imports: Option< &str >,
+    runtime: RuntimeKind,
+    module_name: Option< &str >
+) -> String {
     let filename = wasm_path.file_name().unwrap().to_str().unwrap();
-    let module_name = to_js_identifier( wasm_path.file_stem().unwrap().to_str().unwrap() );
+    let module_name = match module_name {
+        Some( module_name ) => module_name.to_owned(),
+        None => to_js_identifier( wasm_path.file_stem().unwrap().to_str().unwrap() )
+    };
 
     let mut snippets_js = String::new();
     for snippet in snippets {
@@ -75,9 +112,12 @@ pub fn generate_js( wasm_path: &Path, snippets: &[JsSnippet] ) -> String {
 
     let handlebars = Handlebars::new();
     let mut template_data = BTreeMap::new();
-    template_data.insert( "wasm_filename", filename.to_owned() );
-    template_data.insert( "module_name", module_name );
-    template_data.insert( "snippets", snippets_js.trim().to_owned() );
+    template_data.insert( "wasm_filename", json!( filename ) );
+    template_data.insert( "module_name", json!( module_name ) );
+    template_data.insert( "snippets", json!( snippets_js.trim() ) );
+    template_data.insert( "host_imports", json!( host_imports.unwrap_or( "" ).trim() ) );
+    template_data.insert( "es6", json!( runtime == RuntimeKind::LibraryEs6 ) );
+    template_data.insert( "web_extension", json!( runtime == RuntimeKind::WebExtension ) );
     let output = handlebars.template_render( RUNTIME_TEMPLATE, &template_data ).unwrap();
 
     output
@@ -0,0 +1,78 @@
+use std::path::Path;
+use std::fs;
+use std::io::{self, Write};
+
+use parity_wasm;
+use parity_wasm::elements as pw;
+use parity_wasm::elements::Serialize;
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct SectionSizes {
+    pub code: usize,
+    pub data: usize,
+    pub custom: usize,
+    /// Every other section, plus the module's header.
+    pub other: usize,
+    pub total: usize
+}
+
+/// Breaks down a serialized module of `total` bytes by its sections.
+fn section_sizes( module: pw::Module, total: usize ) -> SectionSizes {
+    let mut sizes = SectionSizes { code: 0, data: 0, custom: 0, other: 0, total };
+    for section in module.sections().iter().cloned() {
+        let bucket = match section {
+            pw::Section::Code( _ ) => &mut sizes.code,
+            pw::Section::Data( _ ) => &mut sizes.data,
+            pw::Section::Custom( _ ) => &mut sizes.custom,
+            _ => continue
+        };
+
+        let mut buffer = Vec::new();
+        section.serialize( &mut buffer ).unwrap();
+        *bucket += buffer.len();
+    }
+
+    sizes.other = total - sizes.code - sizes.data - sizes.custom;
+    sizes
+}
+
+pub fn print_wasm_size( path: &Path, as_json: bool ) {
+    let total = fs::metadata( path ).unwrap().len() as usize;
+    let sizes = section_sizes( parity_wasm::deserialize_file( path ).unwrap(), total );
+
+    if as_json {
+        let value = json!({
+            "reason": "wasm-size",
+            "path": path,
+            "total": sizes.total,
+            "sections": {
+                "code": sizes.code,
+                "data": sizes.data,
+                "custom": sizes.custom,
+                "other": sizes.other
+            }
+        });
+
+        writeln!( &mut io::stdout(), "{}", value ).expect( "writeln to stdout failed" );
+        return;
+    }
+
+    println_err!( "    Size of {:?}:", path.file_name().unwrap() );
+    for &(name, size) in &[ ("code", sizes.code), ("data", sizes.data), ("custom", sizes.custom), ("other", sizes.other), ("total", sizes.total) ] {
+        let percentage = if total == 0 { 0.0 } else { size as f64 * 100.0 / total as f64 };
+        println_err!( "    {:<8}{:>10}  {:>5.1}%", name, size, percentage );
+    }
+}
+
+#[test]
+fn test_section_sizes() {
+    let bytes = [
+        0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00,
+        // A custom section named `test` with no payload.
+        0x00, 0x05, 0x04, 0x74, 0x65, 0x73, 0x74
+    ];
+
+    let module: pw::Module = pw::deserialize_buffer( &bytes ).unwrap();
+    let sizes = section_sizes( module, bytes.len() );
+    assert_eq!( sizes, SectionSizes { code: 0, data: 0, custom: 7, other: 8, total: 15 } );
+}
@@ -0,0 +1,62 @@
+use std::fmt::Write;
+
+use wasm_context::{
+    FunctionKind,
+    ImportExport,
+    Context
+};
+
+pub fn is_identifier( name: &str ) -> bool {
+    !name.is_empty() &&
+    !name.starts_with( |ch: char| ch.is_digit( 10 ) ) &&
+    name.chars().all( |ch| ch.is_alphanumeric() || ch == '_' || ch == '$' )
+}
+
+/// Generates a TypeScript declaration for every function exported from the module.
+///
+/// Only the raw signatures are known here, so every parameter and
+/// return value is declared as a `number`.
+pub fn generate( ctx: &Context ) -> String {
+    let mut declarations = Vec::new();
+    for function in ctx.functions.values() {
+        let type_index = match *function {
+            FunctionKind::Import { type_index, .. } => type_index,
+            FunctionKind::Definition { type_index, .. } => type_index
+        };
+
+        let ty = match ctx.fn_ty_by_index( type_index ) {
+            Some( ty ) => ty,
+            None => continue
+        };
+
+        // The `__web_*` exports are only meant for our own runtime.
+        for name in function.as_export().names() {
+            if name.starts_with( "__web_" ) || !is_identifier( name ) {
+                continue;
+            }
+
+            let params: Vec< _ > = (0..ty.params.len()).map( |nth| format!( "arg{}: number", nth ) ).collect();
+            let return_type = if ty.return_type.is_some() { "number" } else { "void" };
+            declarations.push( format!( "export function {}({}): {};", name, params.join( ", " ), return_type ) );
+        }
+    }
+
+    declarations.sort();
+
+    let mut output = String::new();
+    writeln!( output, "// Generated by cargo-web; do not edit." ).unwrap();
+    for declaration in declarations {
+        writeln!( output, "{}", declaration ).unwrap();
+    }
+
+    output
+}
+
+#[test]
+fn test_is_identifier() {
+    assert!( is_identifier( "add_numbers" ) );
+    assert!( is_identifier( "$init" ) );
+    assert!( !is_identifier( "1st" ) );
+    assert!( !is_identifier( "foo.bar" ) );
+    assert!( !is_identifier( "" ) );
+}
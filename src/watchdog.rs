@@ -0,0 +1,181 @@
+use std::process::{Child, Command, exit};
+use std::time::{Duration, Instant};
+use std::thread;
+use std::env;
+
+/// The exit code used when `--overall-timeout` is exceeded; the same one GNU `timeout` uses.
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+// Set on the supervised copy of ourselves so that it doesn't start another watchdog.
+const SUPERVISED_ENV: &'static str = "CARGO_WEB_UNDER_WATCHDOG";
+
+pub fn is_supervised() -> bool {
+    env::var_os( SUPERVISED_ENV ).is_some()
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::process::{Child, Command};
+    use std::os::unix::process::CommandExt;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_BOOL_INIT, ATOMIC_USIZE_INIT};
+    use std::io;
+
+    use libc;
+
+    static PROCESS_GROUP: AtomicUsize = ATOMIC_USIZE_INIT;
+    // A signal which arrived before the child was spawned.
+    static PENDING_SIGNAL: AtomicUsize = ATOMIC_USIZE_INIT;
+    static HAS_HANDED_OVER_TERMINAL: AtomicBool = ATOMIC_BOOL_INIT;
+
+    extern "C" fn forward_signal( signal: libc::c_int ) {
+        let group = PROCESS_GROUP.load( Ordering::SeqCst );
+        if group != 0 {
+            unsafe {
+                libc::kill( -(group as libc::pid_t), signal );
+            }
+        } else {
+            PENDING_SIGNAL.store( signal as usize, Ordering::SeqCst );
+        }
+    }
+
+    // Whether we're in the foreground of a terminal; if so the child has
+    // to take it over, otherwise it would get stopped with `SIGTTIN` as soon
+    // as it tries to read from it, e.g. under `cargo web repl`.
+    fn is_in_foreground() -> bool {
+        unsafe {
+            libc::isatty( libc::STDIN_FILENO ) == 1 && libc::tcgetpgrp( libc::STDIN_FILENO ) == libc::getpgrp()
+        }
+    }
+
+    // The child gets its own process group so that everything it spawns
+    // (cargo, rustc, emcc, node, browsers...) can be killed in one go.
+    pub fn spawn( command: &mut Command ) -> io::Result< Child > {
+        // Since the child is no longer in our process group we have to pass on
+        // Ctrl-C and friends ourselves; this has to be set up before it's
+        // spawned so that we can't get killed and leave it behind.
+        let handler: extern "C" fn( libc::c_int ) = forward_signal;
+        unsafe {
+            libc::signal( libc::SIGINT, handler as libc::sighandler_t );
+            libc::signal( libc::SIGTERM, handler as libc::sighandler_t );
+            libc::signal( libc::SIGHUP, handler as libc::sighandler_t );
+        }
+
+        let hand_over_terminal = is_in_foreground();
+        command.before_exec( move || {
+            unsafe {
+                libc::setpgid( 0, 0 );
+                if hand_over_terminal {
+                    // We're now in a background process group, which would
+                    // otherwise get stopped for changing the terminal's one.
+                    libc::signal( libc::SIGTTOU, libc::SIG_IGN );
+                    libc::tcsetpgrp( libc::STDIN_FILENO, libc::getpid() );
+                    libc::signal( libc::SIGTTOU, libc::SIG_DFL );
+                }
+            }
+            Ok(())
+        });
+
+        let child = command.spawn()?;
+        HAS_HANDED_OVER_TERMINAL.store( hand_over_terminal, Ordering::SeqCst );
+        PROCESS_GROUP.store( child.id() as usize, Ordering::SeqCst );
+
+        let signal = PENDING_SIGNAL.swap( 0, Ordering::SeqCst );
+        if signal != 0 {
+            unsafe {
+                libc::kill( -(child.id() as libc::pid_t), signal as libc::c_int );
+            }
+        }
+
+        Ok( child )
+    }
+
+    /// Takes the terminal back from the child once it's done.
+    pub fn restore_terminal() {
+        if HAS_HANDED_OVER_TERMINAL.load( Ordering::SeqCst ) {
+            unsafe {
+                libc::signal( libc::SIGTTOU, libc::SIG_IGN );
+                libc::tcsetpgrp( libc::STDIN_FILENO, libc::getpgrp() );
+                libc::signal( libc::SIGTTOU, libc::SIG_DFL );
+            }
+        }
+    }
+
+    pub fn kill_tree( child: &mut Child ) {
+        unsafe {
+            libc::kill( -(child.id() as libc::pid_t), libc::SIGKILL );
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::process::{Child, Command, Stdio};
+    use std::io;
+
+    pub fn spawn( command: &mut Command ) -> io::Result< Child > {
+        command.spawn()
+    }
+
+    pub fn restore_terminal() {}
+
+    pub fn kill_tree( child: &mut Child ) {
+        let _ = Command::new( "taskkill" )
+            .arg( "/T" )
+            .arg( "/F" )
+            .arg( "/PID" )
+            .arg( child.id().to_string() )
+            .stdout( Stdio::null() )
+            .stderr( Stdio::null() )
+            .status();
+    }
+}
+
+fn kill( child: &mut Child ) {
+    platform::kill_tree( child );
+    let _ = child.kill();
+    let _ = child.wait();
+    platform::restore_terminal();
+}
+
+/// Reruns the current invocation as a child process and kills it,
+/// along with everything it has spawned, if it takes longer than `timeout`.
+/// Never returns; exits with the child's exit code or with `TIMEOUT_EXIT_CODE`.
+pub fn supervise( timeout: Duration ) -> ! {
+    let executable = env::current_exe().expect( "cannot find the path to the cargo-web executable" );
+    let mut command = Command::new( executable );
+    command
+        .args( env::args_os().skip( 1 ) )
+        .env( SUPERVISED_ENV, "1" );
+
+    let mut child = match platform::spawn( &mut command ) {
+        Ok( child ) => child,
+        Err( error ) => {
+            println_err!( "error: cannot launch cargo-web under the watchdog: {}", error );
+            exit( 101 );
+        }
+    };
+
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok( Some( status ) ) => {
+                platform::restore_terminal();
+                exit( status.code().unwrap_or( 101 ) );
+            },
+            Ok( None ) => {},
+            Err( error ) => {
+                println_err!( "error: cannot wait for cargo-web to finish: {}", error );
+                kill( &mut child );
+                exit( 101 );
+            }
+        }
+
+        if start.elapsed() >= timeout {
+            println_err!( "error: cargo-web didn't finish within the overall timeout of {}s; killing it", timeout.as_secs() );
+            kill( &mut child );
+            exit( TIMEOUT_EXIT_CODE );
+        }
+
+        thread::sleep( Duration::from_millis( 100 ) );
+    }
+}